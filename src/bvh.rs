@@ -0,0 +1,271 @@
+//! Bounding volume hierarchy over the objects of a world.
+//!
+//! Nodes are split with the surface area heuristic and stored depth first in
+//! one flat array. The first child of an interior node follows it directly,
+//! and each leaf covers a contiguous run of objects, which `Bvh::build`
+//! reorders to match.
+
+use crate::intersections::{HitRecord, Hittable};
+use crate::types::{Point3, Ray, Vec3};
+
+/// Axis-aligned bounding box.
+#[derive(Clone, Copy, Debug)]
+pub struct Aabb {
+    pub min: Point3,
+    pub max: Point3,
+}
+
+impl Aabb {
+    pub fn new(min: Point3, max: Point3) -> Self {
+        Aabb { min, max }
+    }
+
+    /// A box containing nothing, the identity of `join`.
+    pub fn empty() -> Self {
+        Aabb { min: Point3::repeat(f32::INFINITY), max: Point3::repeat(f32::NEG_INFINITY) }
+    }
+
+    #[must_use]
+    pub fn join(&self, other: &Aabb) -> Aabb {
+        Aabb { min: self.min.inf(&other.min), max: self.max.sup(&other.max) }
+    }
+
+    #[must_use]
+    pub fn grow(&self, point: &Point3) -> Aabb {
+        Aabb { min: self.min.inf(point), max: self.max.sup(point) }
+    }
+
+    pub fn center(&self) -> Point3 {
+        0.5 * (self.min + self.max)
+    }
+
+    pub fn surface_area(&self) -> f32 {
+        let size = self.max - self.min;
+        if size.min() < 0.0 {
+            return 0.0;
+        }
+        2.0 * (size.x * size.y + size.y * size.z + size.z * size.x)
+    }
+
+    /// Distance along the ray at which it enters the box, if it does so
+    /// between `t_min` and `t_max`. Takes the reciprocal of the ray direction.
+    pub fn hit(&self, origin: &Point3, inv_direction: &Vec3, t_min: f32, t_max: f32) -> Option<f32> {
+        let (mut t_min, mut t_max) = (t_min, t_max);
+        for axis in 0..3 {
+            let t0 = (self.min[axis] - origin[axis]) * inv_direction[axis];
+            let t1 = (self.max[axis] - origin[axis]) * inv_direction[axis];
+            // `max` and `min` ignore the NaN of a ray lying in a slab's plane
+            t_min = t_min.max(t0.min(t1));
+            t_max = t_max.min(t0.max(t1));
+        }
+        if t_min <= t_max { Some(t_min) } else { None }
+    }
+}
+
+pub trait Bounded {
+    fn aabb(&self) -> Aabb;
+}
+
+/// Number of buckets the centroids are sorted into when looking for a split.
+const BINS: usize = 16;
+/// Leaves never hold more objects than this, even when splitting costs more.
+const MAX_LEAF_SIZE: usize = 4;
+/// Cost of visiting a node, relative to testing one object.
+const TRAVERSAL_COST: f32 = 0.5;
+/// Deeper nodes are split at the median instead, which keeps any
+/// tree shallow enough for the traversal stack.
+const MAX_SAH_DEPTH: usize = 64;
+const STACK_SIZE: usize = MAX_SAH_DEPTH + 64;
+
+#[derive(Clone, Copy, Debug)]
+struct Node {
+    aabb: Aabb,
+    /// First object of a leaf, or the second child of an interior node.
+    offset: u32,
+    /// Number of objects in a leaf, zero for an interior node.
+    count: u16,
+    /// Axis an interior node was split along.
+    axis: u8,
+}
+
+/// Bounds of one object, gathered once before building.
+struct Item {
+    index: usize,
+    aabb: Aabb,
+    center: Point3,
+}
+
+#[derive(Default)]
+pub struct Bvh {
+    nodes: Vec<Node>,
+}
+
+impl Bvh {
+    /// Builds a hierarchy over `objects`, reordering them so that every leaf
+    /// refers to a contiguous range.
+    pub fn build<T: Bounded>(objects: &mut Vec<T>) -> Bvh {
+        let mut items: Vec<Item> = objects
+            .iter()
+            .enumerate()
+            .map(|(index, object)| {
+                let aabb = object.aabb();
+                Item { index, aabb, center: aabb.center() }
+            })
+            .collect();
+
+        let mut bvh = Bvh { nodes: Vec::with_capacity(2 * items.len()) };
+        if !items.is_empty() {
+            bvh.build_node(&mut items, 0, 0);
+        }
+
+        let mut rank = vec![0; items.len()];
+        for (position, item) in items.iter().enumerate() {
+            rank[item.index] = position;
+        }
+        let mut ranked: Vec<(usize, T)> = rank.into_iter().zip(objects.drain(..)).collect();
+        ranked.sort_unstable_by_key(|(position, _)| *position);
+        objects.extend(ranked.into_iter().map(|(_, object)| object));
+
+        bvh
+    }
+
+    /// Appends the subtree for `items`, which start at object `first`.
+    fn build_node(&mut self, items: &mut [Item], first: usize, depth: usize) {
+        let aabb = items.iter().fold(Aabb::empty(), |aabb, item| aabb.join(&item.aabb));
+        let node = self.nodes.len();
+        self.nodes.push(Node { aabb, offset: first as u32, count: items.len() as u16, axis: 0 });
+        if items.len() == 1 {
+            return;
+        }
+
+        let bounds = items.iter().fold(Aabb::empty(), |bounds, item| bounds.grow(&item.center));
+        let axis = (bounds.max - bounds.min).imax();
+        let mid = if depth < MAX_SAH_DEPTH {
+            match Self::split(items, &aabb, &bounds, axis) {
+                Some(mid) => mid,
+                None if items.len() <= MAX_LEAF_SIZE => return,
+                None => Self::split_median(items, axis),
+            }
+        } else {
+            Self::split_median(items, axis)
+        };
+
+        let (left, right) = items.split_at_mut(mid);
+        self.build_node(left, first, depth + 1);
+        let second = self.nodes.len() as u32;
+        self.build_node(right, first + mid, depth + 1);
+        self.nodes[node] = Node { aabb, offset: second, count: 0, axis: axis as u8 };
+    }
+
+    /// Partitions `items` where the surface area heuristic finds a split
+    /// along `axis` cheaper than a leaf, and returns the size of the first part.
+    fn split(items: &mut [Item], aabb: &Aabb, centers: &Aabb, axis: usize) -> Option<usize> {
+        let extent = centers.max[axis] - centers.min[axis];
+        if extent <= 0.0 {
+            return None;
+        }
+
+        let bin_of = |item: &Item| {
+            let offset = (item.center[axis] - centers.min[axis]) / extent;
+            ((offset * BINS as f32) as usize).min(BINS - 1)
+        };
+        let mut bins = [(Aabb::empty(), 0usize); BINS];
+        for item in items.iter() {
+            let bin = &mut bins[bin_of(item)];
+            bin.0 = bin.0.join(&item.aabb);
+            bin.1 += 1;
+        }
+
+        // Cost of every split between bins, sweeping in from both sides
+        let mut costs = [(0.0, 0); BINS - 1];
+        let (mut below, mut below_count) = (Aabb::empty(), 0);
+        for (cost, bin) in costs.iter_mut().zip(&bins) {
+            below = below.join(&bin.0);
+            below_count += bin.1;
+            *cost = (below.surface_area() * below_count as f32, below_count);
+        }
+        let (mut above, mut above_count) = (Aabb::empty(), 0);
+        for (cost, bin) in costs.iter_mut().zip(&bins[1..]).rev() {
+            above = above.join(&bin.0);
+            above_count += bin.1;
+            cost.0 += above.surface_area() * above_count as f32;
+        }
+
+        let (best, best_cost) = costs
+            .iter()
+            .enumerate()
+            .filter(|(_, (_, count))| *count > 0 && *count < items.len())
+            .min_by(|a, b| a.1.0.total_cmp(&b.1.0))
+            .map(|(bin, (cost, _))| (bin, TRAVERSAL_COST + cost / aabb.surface_area()))?;
+        if items.len() <= MAX_LEAF_SIZE && best_cost >= items.len() as f32 {
+            return None;
+        }
+
+        let mut mid = 0;
+        for i in 0..items.len() {
+            if bin_of(&items[i]) <= best {
+                items.swap(i, mid);
+                mid += 1;
+            }
+        }
+        Some(mid)
+    }
+
+    /// Partitions `items` into halves along `axis`.
+    fn split_median(items: &mut [Item], axis: usize) -> usize {
+        let mid = items.len() / 2;
+        items.select_nth_unstable_by(mid, |a, b| a.center[axis].total_cmp(&b.center[axis]));
+        mid
+    }
+
+    /// Closest hit among `objects`, which must be the slice `build` reordered.
+    pub fn hit<T: Hittable>(&self, objects: &[T], ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+        if self.nodes.is_empty() {
+            return None;
+        }
+
+        let origin = ray.origin();
+        let direction = ray.direction();
+        let inv_direction = direction.map(f32::recip);
+        let mut closest_t = t_max;
+        let mut closest_hit = None;
+
+        let mut stack = [0u32; STACK_SIZE];
+        let mut stack_len = 0;
+        let mut current = 0;
+        loop {
+            let node = &self.nodes[current as usize];
+            if node.aabb.hit(&origin, &inv_direction, t_min, closest_t).is_some() {
+                if node.count > 0 {
+                    let first = node.offset as usize;
+                    for object in &objects[first..first + node.count as usize] {
+                        if let Some(hit) = object.hit(ray, t_min, closest_t) {
+                            closest_t = hit.t;
+                            closest_hit = Some(hit);
+                        }
+                    }
+                } else {
+                    // Visit the child nearer along the split axis first, so
+                    // that its hits prune the farther one
+                    let (near, far) = if direction[node.axis as usize] < 0.0 {
+                        (node.offset, current + 1)
+                    } else {
+                        (current + 1, node.offset)
+                    };
+                    stack[stack_len] = far;
+                    stack_len += 1;
+                    current = near;
+                    continue;
+                }
+            }
+
+            if stack_len == 0 {
+                break;
+            }
+            stack_len -= 1;
+            current = stack[stack_len];
+        }
+
+        closest_hit
+    }
+}
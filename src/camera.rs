@@ -1,5 +1,6 @@
 use crate::types::{Point3, Vec3, Ray};
-use crate::random::{Vector, rand_range};
+use crate::random::concentric_disk;
+use crate::sampler::{PixelSampler, Sampler};
 
 pub struct Camera {
     pub origin: Point3,
@@ -45,11 +46,13 @@ impl Camera {
         }
     }
 
-    pub fn get_ray(&self, u: f32, v: f32) -> Ray {
-        let rd = self.lens_radius * Vec3::rand_in_unit_disk();
+    /// Ray through the point `(u, v)` of the viewport, from a point on the lens
+    /// and at a moment of the shutter interval drawn from `sampler`.
+    pub fn get_ray(&self, u: f32, v: f32, sampler: &mut PixelSampler) -> Ray {
+        let rd = self.lens_radius * concentric_disk(sampler.get_2d());
         let offset = self.u * rd.x + self.v * rd.y;
 
         Ray::new(self.origin + offset, self.lower_left_corner + u * self.horizontal + v * self.vertical - self.origin - offset,
-        rand_range(self.t0, self.t1))
+        self.t0 + sampler.get_1d() * (self.t1 - self.t0))
     }
 }
\ No newline at end of file
@@ -0,0 +1,462 @@
+use std::path::PathBuf;
+
+use clap::error::ErrorKind;
+use clap::{CommandFactory, Parser, ValueEnum};
+
+use raytracer::hdr::ExrPrecision;
+use raytracer::image::ImageFormat;
+use raytracer::render::{AdaptiveSampling, MisHeuristic};
+use raytracer::filter::{Filter, FilterKind};
+use raytracer::sampler::SamplerKind;
+use raytracer::tile::TileOrder;
+use raytracer::scenes;
+use raytracer::tonemap::{ToneMapOperator, ToneMapping, TransferFunction};
+use raytracer::Color;
+
+const DEFAULT_WIDTH: u32 = 500;
+const DEFAULT_ASPECT_RATIO: f32 = 16.0 / 9.0;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// 8-bit PNG
+    Png,
+    /// Binary P6 portable pixmap
+    Ppm,
+    /// ASCII P3 portable pixmap
+    PpmAscii,
+    /// EXR with half float channels
+    Exr,
+    /// EXR with full float channels
+    ExrFloat,
+    /// Radiance RGBE
+    Hdr,
+    /// Portable float map
+    Pfm,
+}
+
+impl From<OutputFormat> for ImageFormat {
+    fn from(format: OutputFormat) -> Self {
+        match format {
+            OutputFormat::Png => ImageFormat::Png,
+            OutputFormat::Ppm => ImageFormat::Ppm,
+            OutputFormat::PpmAscii => ImageFormat::PpmAscii,
+            OutputFormat::Exr => ImageFormat::Exr(ExrPrecision::Half),
+            OutputFormat::ExrFloat => ImageFormat::Exr(ExrPrecision::Float),
+            OutputFormat::Hdr => ImageFormat::Hdr,
+            OutputFormat::Pfm => ImageFormat::Pfm,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+pub enum ToneMap {
+    /// Clip values above 1
+    Clamp,
+    Reinhard,
+    /// Reinhard mapping --white-point to 1
+    ReinhardExtended,
+    /// ACES filmic approximation
+    Aces,
+    /// Hable (Uncharted 2) filmic curve
+    Hable,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Mis {
+    Balance,
+    Power,
+}
+
+impl From<Mis> for MisHeuristic {
+    fn from(mis: Mis) -> Self {
+        match mis {
+            Mis::Balance => MisHeuristic::Balance,
+            Mis::Power => MisHeuristic::Power,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Sampler {
+    /// Independent uniform random numbers
+    Independent,
+    /// Jittered samples, one per stratum
+    Stratified,
+    /// Randomly shifted Halton sequence
+    Halton,
+    /// Owen-scrambled Sobol sequence
+    Sobol,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+pub enum PixelFilter {
+    /// Plain average of the samples inside each pixel (radius 0.5)
+    Box,
+    /// Linear falloff (radius 1)
+    Tent,
+    /// Gaussian falloff (radius 1.5)
+    Gaussian,
+    /// Mitchell-Netravali cubic, slightly sharpening (radius 2)
+    Mitchell,
+    /// Windowed sinc, the sharpest (radius 3)
+    Lanczos,
+}
+
+impl From<PixelFilter> for FilterKind {
+    fn from(filter: PixelFilter) -> Self {
+        match filter {
+            PixelFilter::Box => FilterKind::Box,
+            PixelFilter::Tent => FilterKind::Tent,
+            PixelFilter::Gaussian => FilterKind::Gaussian,
+            PixelFilter::Mitchell => FilterKind::Mitchell,
+            PixelFilter::Lanczos => FilterKind::Lanczos,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Tiles {
+    /// Row by row from the top left
+    Scanline,
+    /// Outwards from the centre of the image
+    Spiral,
+    /// Along a Hilbert curve, keeping consecutive tiles close together
+    Hilbert,
+}
+
+impl From<Tiles> for TileOrder {
+    fn from(tiles: Tiles) -> Self {
+        match tiles {
+            Tiles::Scanline => TileOrder::Scanline,
+            Tiles::Spiral => TileOrder::Spiral,
+            Tiles::Hilbert => TileOrder::Hilbert,
+        }
+    }
+}
+
+impl From<Sampler> for SamplerKind {
+    fn from(sampler: Sampler) -> Self {
+        match sampler {
+            Sampler::Independent => SamplerKind::Independent,
+            Sampler::Stratified => SamplerKind::Stratified,
+            Sampler::Halton => SamplerKind::Halton,
+            Sampler::Sobol => SamplerKind::Sobol,
+        }
+    }
+}
+
+#[derive(Parser, Debug)]
+#[command(about = "Renders a built-in scene or a scene file to an image file")]
+pub struct Args {
+    /// Built-in scene to render
+    #[arg(short, long, default_value = "two_perlin_spheres",
+          value_parser = clap::builder::PossibleValuesParser::new(scenes::NAMES))]
+    pub scene: String,
+
+    /// TOML scene description to render instead of a built-in scene
+    #[arg(long, conflicts_with = "scene")]
+    pub scene_file: Option<PathBuf>,
+
+    /// Equirectangular .hdr or .exr image lighting the scene, replacing its background
+    #[arg(long)]
+    pub environment: Option<PathBuf>,
+
+    /// Rotation of the environment map about the vertical axis, in degrees
+    #[arg(long, default_value_t = 0.0, requires = "environment", allow_negative_numbers = true,
+          value_parser = parse_finite)]
+    pub environment_rotation: f32,
+
+    /// Brightness multiplier for the environment map
+    #[arg(long, default_value_t = 1.0, requires = "environment", value_parser = parse_positive)]
+    pub environment_intensity: f32,
+
+    /// Image width in pixels (derived from height and aspect ratio if omitted)
+    #[arg(short = 'W', long, value_parser = clap::value_parser!(u32).range(2..))]
+    pub width: Option<u32>,
+
+    /// Image height in pixels (derived from width and aspect ratio if omitted)
+    #[arg(short = 'H', long, value_parser = clap::value_parser!(u32).range(2..))]
+    pub height: Option<u32>,
+
+    /// Aspect ratio as `W:H` or a decimal number, e.g. `16:9` or `1.5`
+    #[arg(short, long, value_parser = parse_aspect_ratio)]
+    pub aspect: Option<f32>,
+
+    /// Number of samples per pixel
+    #[arg(long, default_value_t = 50, value_parser = clap::value_parser!(u32).range(1..))]
+    pub spp: u32,
+
+    /// Let pixels stop sampling once the standard error of their brightness
+    /// falls below this fraction of it, with --spp as the limit
+    #[arg(long, value_parser = parse_positive)]
+    pub adaptive_threshold: Option<f32>,
+
+    /// Samples every pixel takes before adaptive sampling may stop it, at
+    /// most --spp [default: 16, or --spp if lower]
+    #[arg(long, requires = "adaptive_threshold", value_parser = clap::value_parser!(u32).range(2..))]
+    pub min_spp: Option<u32>,
+
+    /// Also write a heatmap of the samples each pixel took to this path
+    #[arg(long, requires = "adaptive_threshold")]
+    pub spp_heatmap: Option<PathBuf>,
+
+    /// Maximum number of ray bounces (unlimited if omitted, leaving it to Russian roulette)
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    pub max_depth: Option<u32>,
+
+    /// Number of bounces before Russian roulette may end a path
+    #[arg(long, default_value_t = 3)]
+    pub rr_depth: u32,
+
+    /// Heuristic weighing light samples against BSDF samples
+    #[arg(long, value_enum, default_value_t = Mis::Power)]
+    pub mis: Mis,
+
+    /// Seed for all random numbers; the same seed renders the same image
+    #[arg(long, default_value_t = 0)]
+    pub seed: u64,
+
+    /// How pixel, lens, time and scattering samples are placed
+    #[arg(long, value_enum, default_value_t = Sampler::Sobol)]
+    pub sampler: Sampler,
+
+    /// Reconstruction filter spreading each sample over the pixels around it
+    #[arg(long, value_enum, default_value_t = PixelFilter::Box)]
+    pub filter: PixelFilter,
+
+    /// Radius of the reconstruction filter in pixels, at least 0.5 (the filter's own default if omitted)
+    #[arg(long, value_parser = parse_positive)]
+    pub filter_radius: Option<f32>,
+
+    /// Width and height of the square tiles the image is rendered in, in pixels
+    #[arg(long, default_value_t = 32, value_parser = clap::value_parser!(u32).range(1..))]
+    pub tile_size: u32,
+
+    /// Order in which the tiles are rendered
+    #[arg(long, value_enum, default_value_t = Tiles::Hilbert)]
+    pub tile_order: Tiles,
+
+    /// Render in passes doubling the samples per pixel, rewriting the output image after each
+    #[arg(long)]
+    pub progressive: bool,
+
+    /// Output image path
+    #[arg(short, long, default_value = "target/image.png")]
+    pub output: PathBuf,
+
+    /// Output image format (derived from the output extension if omitted)
+    #[arg(short, long, value_enum)]
+    pub format: Option<OutputFormat>,
+
+    /// Number of worker threads (defaults to one per logical CPU)
+    #[arg(short = 'j', long, value_parser = clap::value_parser!(u32).range(1..))]
+    pub threads: Option<u32>,
+
+    /// Exposure adjustment in stops, applied before tone mapping
+    #[arg(long, default_value_t = 0.0, allow_negative_numbers = true, value_parser = parse_finite)]
+    pub exposure: f32,
+
+    /// Colour that should come out neutral, as `R,G,B`
+    #[arg(long, value_parser = parse_color)]
+    pub white_balance: Option<Color>,
+
+    /// Tone mapping operator for 8-bit output formats
+    #[arg(long, value_enum, default_value_t = ToneMap::Clamp)]
+    pub tonemap: ToneMap,
+
+    /// Radiance mapped to white by the extended Reinhard operator
+    #[arg(long, default_value_t = 4.0, value_parser = parse_positive)]
+    pub white_point: f32,
+
+    /// Encode with a plain power-law gamma instead of the sRGB curve
+    #[arg(long, value_parser = parse_positive)]
+    pub gamma: Option<f32>,
+
+    /// Open the image in the system viewer once it is written
+    #[arg(long)]
+    pub open: bool,
+}
+
+pub enum SceneSource {
+    Builtin(String),
+    File(PathBuf),
+}
+
+/// An environment map given on the command line.
+pub struct EnvironmentSettings {
+    pub path: PathBuf,
+    pub rotation: f32,
+    pub intensity: f32,
+}
+
+pub struct Settings {
+    pub scene: SceneSource,
+    pub environment: Option<EnvironmentSettings>,
+    pub image_width: u32,
+    pub image_height: u32,
+    pub samples_per_pixel: u32,
+    pub adaptive: Option<AdaptiveSampling>,
+    pub spp_heatmap: Option<PathBuf>,
+    pub max_depth: Option<u32>,
+    pub rr_min_depth: u32,
+    pub mis_heuristic: MisHeuristic,
+    pub seed: u64,
+    pub sampler: SamplerKind,
+    pub filter: Filter,
+    pub tile_size: u32,
+    pub tile_order: TileOrder,
+    pub progressive: bool,
+    pub output: PathBuf,
+    pub format: ImageFormat,
+    pub tone_mapping: ToneMapping,
+    pub threads: Option<usize>,
+    pub open: bool,
+}
+
+impl Args {
+    /// Resolves the image dimensions and output format, reporting any
+    /// inconsistent combination of arguments as a usage error.
+    pub fn settings(self) -> Result<Settings, clap::Error> {
+        let (image_width, image_height, aspect_ratio) = match (self.width, self.height, self.aspect) {
+            (Some(_), Some(_), Some(_)) => {
+                return Err(usage_error(ErrorKind::ArgumentConflict,
+                                       "--width, --height and --aspect cannot all be given at once"));
+            }
+            (Some(width), Some(height), None) => (width, height, width as f32 / height as f32),
+            (width, None, aspect) => {
+                let width = width.unwrap_or(DEFAULT_WIDTH);
+                let aspect = aspect.unwrap_or(DEFAULT_ASPECT_RATIO);
+                (width, (width as f32 / aspect).round() as u32, aspect)
+            }
+            (None, Some(height), aspect) => {
+                let aspect = aspect.unwrap_or(DEFAULT_ASPECT_RATIO);
+                ((height as f32 * aspect).round() as u32, height, aspect)
+            }
+        };
+
+        if image_width < 2 || image_height < 2 {
+            return Err(usage_error(ErrorKind::ValueValidation,
+                                   format!("aspect ratio {aspect_ratio} gives a degenerate \
+                                            {image_width}x{image_height} image")));
+        }
+
+        let format = match self.format {
+            Some(format) => format.into(),
+            None => ImageFormat::from_path(&self.output).ok_or_else(|| {
+                usage_error(ErrorKind::ValueValidation,
+                            format!("cannot infer the image format of '{}', use --format",
+                                    self.output.display()))
+            })?,
+        };
+
+        let operator = match self.tonemap {
+            ToneMap::Clamp => ToneMapOperator::Clamp,
+            ToneMap::Reinhard => ToneMapOperator::Reinhard,
+            ToneMap::ReinhardExtended => ToneMapOperator::ExtendedReinhard { white: self.white_point },
+            ToneMap::Aces => ToneMapOperator::Aces,
+            ToneMap::Hable => ToneMapOperator::Hable,
+        };
+
+        let tone_mapping = ToneMapping {
+            exposure: self.exposure,
+            white_balance: self.white_balance.unwrap_or_else(|| Color::new(1.0, 1.0, 1.0)),
+            operator,
+            transfer: self.gamma.map_or(TransferFunction::Srgb, TransferFunction::Gamma),
+        };
+
+        let mut filter = Filter::new(self.filter.into());
+        if let Some(radius) = self.filter_radius {
+            filter = filter.with_radius(radius);
+        }
+
+        let min_samples = match self.min_spp {
+            Some(min_spp) if min_spp > self.spp => {
+                return Err(usage_error(ErrorKind::ValueValidation,
+                                       format!("--min-spp {min_spp} exceeds --spp {}", self.spp)));
+            }
+            Some(min_spp) => min_spp,
+            None => AdaptiveSampling::default().min_samples.min(self.spp),
+        };
+
+        let (rotation, intensity) = (self.environment_rotation, self.environment_intensity);
+        Ok(Settings {
+            scene: match self.scene_file {
+                Some(path) => SceneSource::File(path),
+                None => SceneSource::Builtin(self.scene),
+            },
+            environment: self.environment.map(|path| EnvironmentSettings {
+                path,
+                rotation,
+                intensity,
+            }),
+            image_width,
+            image_height,
+            samples_per_pixel: self.spp,
+            adaptive: self.adaptive_threshold.map(|threshold| AdaptiveSampling {
+                min_samples,
+                threshold,
+            }),
+            spp_heatmap: self.spp_heatmap,
+            max_depth: self.max_depth,
+            rr_min_depth: self.rr_depth,
+            mis_heuristic: self.mis.into(),
+            seed: self.seed,
+            sampler: self.sampler.into(),
+            filter,
+            tile_size: self.tile_size,
+            tile_order: self.tile_order.into(),
+            progressive: self.progressive,
+            output: self.output,
+            format,
+            tone_mapping,
+            threads: self.threads.map(|threads| threads as usize),
+            open: self.open,
+        })
+    }
+}
+
+fn usage_error(kind: ErrorKind, message: impl std::fmt::Display) -> clap::Error {
+    Args::command().error(kind, message)
+}
+
+fn parse_aspect_ratio(s: &str) -> Result<f32, String> {
+    let ratio = if let Some((w, h)) = s.split_once(':') {
+        let w = w.trim().parse::<f32>().map_err(|e| format!("invalid width '{w}': {e}"))?;
+        let h = h.trim().parse::<f32>().map_err(|e| format!("invalid height '{h}': {e}"))?;
+        w / h
+    } else {
+        s.trim().parse::<f32>().map_err(|e| e.to_string())?
+    };
+
+    if ratio.is_finite() && ratio > 0.0 {
+        Ok(ratio)
+    } else {
+        Err(format!("'{s}' is not a positive finite ratio"))
+    }
+}
+
+fn parse_finite(s: &str) -> Result<f32, String> {
+    let value = s.trim().parse::<f32>().map_err(|e| e.to_string())?;
+    if value.is_finite() {
+        Ok(value)
+    } else {
+        Err(format!("'{s}' is not a finite number"))
+    }
+}
+
+fn parse_positive(s: &str) -> Result<f32, String> {
+    let value = parse_finite(s)?;
+    if value > 0.0 {
+        Ok(value)
+    } else {
+        Err(format!("'{s}' must be greater than zero"))
+    }
+}
+
+fn parse_color(s: &str) -> Result<Color, String> {
+    let components = s.split(',').map(parse_positive).collect::<Result<Vec<_>, _>>()?;
+    match components[..] {
+        [r, g, b] => Ok(Color::new(r, g, b)),
+        _ => Err(format!("expected three comma separated components, found {}", components.len())),
+    }
+}
@@ -0,0 +1,214 @@
+use std::f32::consts::PI;
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::random::uniform_sphere_direction;
+use crate::sampler::ONE_MINUS_EPSILON;
+use crate::tonemap::{luminance, srgb_to_linear};
+use crate::types::{Color, Ray, Vec3};
+
+/// Light arriving from infinitely far away, seen by rays that leave the scene.
+#[derive(Clone)]
+pub enum Environment {
+    Constant(Color),
+    /// White-to-blue gradient along the ray's vertical direction.
+    Gradient,
+    Map(EnvironmentMap),
+}
+
+impl Environment {
+    pub fn color(&self, ray: &Ray) -> Color {
+        match self {
+            Environment::Constant(color) => *color,
+            Environment::Gradient => {
+                let unit_direction = ray.direction().normalize();
+                let t = 0.5 * (unit_direction.y + 1.0);
+                (1.0 - t) * Color::new(1.0, 1.0, 1.0) + t * Color::new(0.5, 0.7, 1.0)
+            }
+            Environment::Map(map) => map.color(&ray.direction()),
+        }
+    }
+
+    /// Whether any light comes from the environment, making it worth sampling directly.
+    pub fn is_light(&self) -> bool {
+        match self {
+            Environment::Constant(color) => color.max() > 0.0,
+            Environment::Gradient => true,
+            Environment::Map(map) => map.data.total_weight > 0.0 && map.intensity > 0.0,
+        }
+    }
+
+    /// Solid angle density with which `sample` produces `direction`.
+    pub fn pdf_value(&self, direction: &Vec3) -> f32 {
+        match self {
+            Environment::Map(map) => map.pdf_value(direction),
+            _ => 1.0 / (4.0 * PI),
+        }
+    }
+
+    /// Direction towards the environment placed by `u`, a point of the unit
+    /// square, favouring bright regions of a map.
+    pub fn sample(&self, u: (f32, f32)) -> Vec3 {
+        match self {
+            Environment::Map(map) => map.sample(u),
+            _ => uniform_sphere_direction(u),
+        }
+    }
+}
+
+/// An equirectangular (latitude-longitude) image of the surroundings.
+///
+/// The top row looks along `+y` and the centre column along `-z`. Directions
+/// are importance sampled in proportion to each texel's luminance, weighted
+/// by the solid angle it covers.
+#[derive(Clone)]
+pub struct EnvironmentMap {
+    data: Arc<MapData>,
+    /// Rotation about the vertical axis, in radians.
+    rotation: f32,
+    pub intensity: f32,
+}
+
+struct MapData {
+    width: usize,
+    height: usize,
+    texels: Vec<Color>,
+    /// Sampling weight of every texel, row by row.
+    weights: Vec<f32>,
+    total_weight: f32,
+    /// Normalised cumulative weights of the rows.
+    row_cdf: Vec<f32>,
+    /// Normalised cumulative weights of the texels within each row.
+    column_cdfs: Vec<f32>,
+}
+
+impl EnvironmentMap {
+    /// Decodes a Radiance HDR or EXR image. Low dynamic range formats are
+    /// accepted too and treated as sRGB encoded.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the file cannot be read or decoded.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, ::image::ImageError> {
+        let decoded = ::image::open(path)?;
+        let linear = matches!(decoded.color(), ::image::ColorType::Rgb32F | ::image::ColorType::Rgba32F);
+        let decoded = decoded.into_rgb32f();
+        let texels = decoded
+            .pixels()
+            .map(|p| {
+                let color = Color::new(p[0], p[1], p[2]);
+                if linear { color } else { color.map(srgb_to_linear) }
+            })
+            .collect();
+
+        Ok(Self::new(decoded.width() as usize, decoded.height() as usize, texels))
+    }
+
+    /// Builds a map from `width * height` linear texels, listed row by row from the top.
+    pub fn new(width: usize, height: usize, texels: Vec<Color>) -> Self {
+        let mut weights = Vec::with_capacity(texels.len());
+        let mut row_cdf = Vec::with_capacity(height);
+        let mut column_cdfs = Vec::with_capacity(texels.len());
+        let mut total_weight = 0.0;
+
+        for (y, row) in texels.chunks(width).enumerate() {
+            let sin_theta = (PI * (y as f32 + 0.5) / height as f32).sin();
+            let start = weights.len();
+            let mut row_weight = 0.0;
+            for texel in row {
+                let weight = luminance(texel.map(|c| c.max(0.0))) * sin_theta;
+                weights.push(weight);
+                row_weight += weight;
+                column_cdfs.push(row_weight);
+            }
+            for c in &mut column_cdfs[start..] {
+                *c = if row_weight > 0.0 { *c / row_weight } else { 1.0 };
+            }
+            total_weight += row_weight;
+            row_cdf.push(total_weight);
+        }
+        for c in &mut row_cdf {
+            *c = if total_weight > 0.0 { *c / total_weight } else { 1.0 };
+        }
+
+        EnvironmentMap {
+            data: Arc::new(MapData { width, height, texels, weights, total_weight, row_cdf, column_cdfs }),
+            rotation: 0.0,
+            intensity: 1.0,
+        }
+    }
+
+    /// Turns the map about the vertical `+y` axis.
+    #[must_use]
+    pub fn with_rotation(mut self, degrees: f32) -> Self {
+        self.rotation = degrees.to_radians();
+        self
+    }
+
+    /// Scales the radiance of every texel.
+    #[must_use]
+    pub fn with_intensity(mut self, intensity: f32) -> Self {
+        self.intensity = intensity;
+        self
+    }
+
+    pub fn color(&self, direction: &Vec3) -> Color {
+        let (x, y) = self.texel(direction);
+        self.data.texels[y * self.data.width + x] * self.intensity
+    }
+
+    pub fn pdf_value(&self, direction: &Vec3) -> f32 {
+        let data = &self.data;
+        if data.total_weight <= 0.0 {
+            return 0.0;
+        }
+
+        let local = rotate_y(&direction.normalize(), -self.rotation);
+        let sin_theta = local.x.hypot(local.z);
+        if sin_theta <= 0.0 {
+            return 0.0;
+        }
+        let (x, y) = self.texel(direction);
+        // Density over the unit square of texture coordinates, converted to
+        // solid angle through the Jacobian of the equirectangular mapping
+        let uv_pdf = data.weights[y * data.width + x] / data.total_weight * (data.width * data.height) as f32;
+        uv_pdf / (2.0 * PI * PI * sin_theta)
+    }
+
+    pub fn sample(&self, choice: (f32, f32)) -> Vec3 {
+        let data = &self.data;
+        let (y, v_offset) = invert_cdf(&data.row_cdf, choice.0);
+        let (x, u_offset) = invert_cdf(&data.column_cdfs[y * data.width..(y + 1) * data.width], choice.1);
+
+        let u = (x as f32 + u_offset) / data.width as f32;
+        let v = (y as f32 + v_offset) / data.height as f32;
+        let phi = 2.0 * PI * (u - 0.5);
+        let theta = PI * v;
+        let local = Vec3::new(theta.sin() * phi.sin(), theta.cos(), -theta.sin() * phi.cos());
+        rotate_y(&local, self.rotation)
+    }
+
+    fn texel(&self, direction: &Vec3) -> (usize, usize) {
+        let local = rotate_y(&direction.normalize(), -self.rotation);
+        let u = 0.5 + local.x.atan2(-local.z) / (2.0 * PI);
+        let v = local.y.clamp(-1.0, 1.0).acos() / PI;
+        let x = ((u * self.data.width as f32) as usize).min(self.data.width - 1);
+        let y = ((v * self.data.height as f32) as usize).min(self.data.height - 1);
+        (x, y)
+    }
+}
+
+/// The entry of `cdf`, a list of ascending upper bounds ending at 1, that `u`
+/// falls into, and how far into it, rescaled to `[0, 1)`.
+fn invert_cdf(cdf: &[f32], u: f32) -> (usize, f32) {
+    let index = cdf.partition_point(|&c| c <= u).min(cdf.len() - 1);
+    let low = if index == 0 { 0.0 } else { cdf[index - 1] };
+    let width = cdf[index] - low;
+    let offset = if width > 0.0 { (u - low) / width } else { 0.5 };
+    (index, offset.clamp(0.0, ONE_MINUS_EPSILON))
+}
+
+fn rotate_y(v: &Vec3, angle: f32) -> Vec3 {
+    let (sin, cos) = angle.sin_cos();
+    Vec3::new(cos * v.x + sin * v.z, v.y, -sin * v.x + cos * v.z)
+}
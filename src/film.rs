@@ -0,0 +1,116 @@
+use crate::filter::Filter;
+use crate::tile::Tile;
+use crate::types::Color;
+
+/// Fraction of their total magnitude below which a pixel's filter weights are
+/// taken to have cancelled out, leaving a filtered value that is mostly noise.
+const MIN_NET_WEIGHT: f32 = 0.1;
+
+/// Filtered sums of the samples landing in a rectangle of the image.
+///
+/// Every sample is spread over the pixels within the filter's radius, so a
+/// pixel's value only settles once all its neighbours have been sampled.
+/// Parts of the image rendered separately are combined with `merge`.
+#[derive(Clone)]
+pub struct Film {
+    /// Pixels covered, counted from the image's top left corner.
+    pub area: Tile,
+    sums: Vec<Color>,
+    weights: Vec<f32>,
+    /// Sums of the weights' absolute values, which only differ from `weights`
+    /// for filters with negative lobes.
+    abs_weights: Vec<f32>,
+    /// Unweighted sums and counts of the samples landing inside each pixel,
+    /// for pixels whose filter weights cancel out.
+    plain_sums: Vec<Color>,
+    counts: Vec<u32>,
+}
+
+impl Film {
+    /// An empty film covering `area`.
+    pub fn new(area: Tile) -> Film {
+        let len = (area.width * area.height) as usize;
+        Film {
+            area,
+            sums: vec![Color::new(0.0, 0.0, 0.0); len],
+            weights: vec![0.0; len],
+            abs_weights: vec![0.0; len],
+            plain_sums: vec![Color::new(0.0, 0.0, 0.0); len],
+            counts: vec![0; len],
+        }
+    }
+
+    /// Adds a sample taken at `(x, y)`, in pixels from the image's top left
+    /// corner, to every pixel of the film within the filter's reach.
+    pub fn add_sample(&mut self, x: f32, y: f32, color: &Color, filter: Filter) {
+        let area = self.area;
+        let (column, row) = ((x - area.left as f32).floor(), (y - area.top as f32).floor());
+        if (0.0..area.width as f32).contains(&column) && (0.0..area.height as f32).contains(&row) {
+            let i = (row as u32 * area.width + column as u32) as usize;
+            self.plain_sums[i] += color;
+            self.counts[i] += 1;
+        }
+
+        let (x, y) = (x - 0.5 - area.left as f32, y - 0.5 - area.top as f32);
+        let x_range = (x - filter.radius).floor().max(0.0) as u32..=((x + filter.radius).floor() as u32).min(area.width - 1);
+        let y_range = (y - filter.radius).floor().max(0.0) as u32..=((y + filter.radius).floor() as u32).min(area.height - 1);
+        for row in y_range {
+            for column in x_range.clone() {
+                let weight = filter.evaluate(x - column as f32, y - row as f32);
+                if weight != 0.0 {
+                    let i = (row * area.width + column) as usize;
+                    self.sums[i] += weight * color;
+                    self.weights[i] += weight;
+                    self.abs_weights[i] += weight.abs();
+                }
+            }
+        }
+    }
+
+    /// Adds the sums of another film of the same image, whose area must lie within this one's.
+    pub fn merge(&mut self, other: &Film) {
+        let (left, top) = (other.area.left - self.area.left, other.area.top - self.area.top);
+        let width = other.area.width as usize;
+        for row in 0..other.area.height {
+            let start = ((top + row) * self.area.width + left) as usize;
+            let other_start = (row * other.area.width) as usize;
+            let sums = self.sums[start..start + width].iter_mut().zip(&other.sums[other_start..other_start + width]);
+            for (sum, other_sum) in sums {
+                *sum += other_sum;
+            }
+            let weights = self.weights[start..start + width].iter_mut().zip(&other.weights[other_start..other_start + width]);
+            for (weight, other_weight) in weights {
+                *weight += other_weight;
+            }
+            let abs_weights = self.abs_weights[start..start + width].iter_mut().zip(&other.abs_weights[other_start..other_start + width]);
+            for (weight, other_weight) in abs_weights {
+                *weight += other_weight;
+            }
+            let plain_sums = self.plain_sums[start..start + width].iter_mut().zip(&other.plain_sums[other_start..other_start + width]);
+            for (sum, other_sum) in plain_sums {
+                *sum += other_sum;
+            }
+            let counts = self.counts[start..start + width].iter_mut().zip(&other.counts[other_start..other_start + width]);
+            for (count, other_count) in counts {
+                *count += other_count;
+            }
+        }
+    }
+
+    /// The filtered value of every pixel, row by row from the top left.
+    /// Where the weights add up to little or nothing, as the negative lobes of
+    /// Mitchell and Lanczos filters can make them at low sample counts, the
+    /// pixel takes the plain average of the samples inside it instead, and
+    /// pixels without any samples are black.
+    pub fn pixels(&self) -> Vec<Color> {
+        (0..self.sums.len())
+            .map(|i| if self.weights[i] > 0.0 && self.weights[i] >= MIN_NET_WEIGHT * self.abs_weights[i] {
+                self.sums[i] / self.weights[i]
+            } else if self.counts[i] > 0 {
+                self.plain_sums[i] / self.counts[i] as f32
+            } else {
+                Color::new(0.0, 0.0, 0.0)
+            })
+            .collect()
+    }
+}
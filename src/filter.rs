@@ -0,0 +1,94 @@
+//! Pixel reconstruction filters, weighing each sample by its distance from
+//! the centres of the pixels around it.
+
+use std::f32::consts::PI;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum FilterKind {
+    /// Equal weight within the radius, the plain average of earlier versions.
+    Box,
+    /// Weight falling linearly to zero at the radius.
+    Tent,
+    /// Gaussian with a standard deviation of a third of the radius, shifted
+    /// down to reach zero at the radius.
+    Gaussian,
+    /// Mitchell and Netravali's cubic with `B = C = 1/3`.
+    Mitchell,
+    /// Sinc windowed by a wider sinc that reaches zero at the radius.
+    Lanczos,
+}
+
+/// A separable filter reaching `radius` pixels out from the sample.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Filter {
+    pub kind: FilterKind,
+    pub radius: f32,
+}
+
+impl Default for Filter {
+    fn default() -> Self {
+        Filter::new(FilterKind::Box)
+    }
+}
+
+impl Filter {
+    /// A filter of the given kind with its customary radius.
+    pub fn new(kind: FilterKind) -> Filter {
+        let radius = match kind {
+            FilterKind::Box => 0.5,
+            FilterKind::Tent => 1.0,
+            FilterKind::Gaussian => 1.5,
+            FilterKind::Mitchell => 2.0,
+            FilterKind::Lanczos => 3.0,
+        };
+        Filter { kind, radius }
+    }
+
+    /// The same filter reaching `radius` pixels out, or half a pixel if that
+    /// is more: any less and samples would miss the pixel they land in.
+    #[must_use]
+    pub fn with_radius(self, radius: f32) -> Filter {
+        Filter { radius: radius.max(0.5), ..self }
+    }
+
+    /// Weight of a sample lying `(x, y)` pixels from a pixel centre. Mitchell
+    /// and Lanczos filters have negative lobes.
+    pub fn evaluate(self, x: f32, y: f32) -> f32 {
+        self.evaluate_1d(x) * self.evaluate_1d(y)
+    }
+
+    fn evaluate_1d(self, x: f32) -> f32 {
+        let r = self.radius;
+        match self.kind {
+            // Half open, so that a sample on the border between two pixels only counts for one
+            FilterKind::Box => if (-r..r).contains(&x) { 1.0 } else { 0.0 },
+            FilterKind::Tent => (r - x.abs()).max(0.0),
+            FilterKind::Gaussian => {
+                let sigma = r / 3.0;
+                let gaussian = |x: f32| (-x * x / (2.0 * sigma * sigma)).exp();
+                (gaussian(x) - gaussian(r)).max(0.0)
+            }
+            FilterKind::Mitchell => mitchell(2.0 * x / r, 1.0 / 3.0, 1.0 / 3.0),
+            FilterKind::Lanczos => if x.abs() < r { sinc(x) * sinc(x / r) } else { 0.0 },
+        }
+    }
+}
+
+/// Mitchell-Netravali cubic over `[-2, 2]`.
+fn mitchell(x: f32, b: f32, c: f32) -> f32 {
+    let x = x.abs();
+    if x < 1.0 {
+        ((12.0 - 9.0 * b - 6.0 * c) * x * x * x + (-18.0 + 12.0 * b + 6.0 * c) * x * x + (6.0 - 2.0 * b)) / 6.0
+    } else if x < 2.0 {
+        ((-b - 6.0 * c) * x * x * x + (6.0 * b + 30.0 * c) * x * x + (-12.0 * b - 48.0 * c) * x
+            + (8.0 * b + 24.0 * c))
+            / 6.0
+    } else {
+        0.0
+    }
+}
+
+fn sinc(x: f32) -> f32 {
+    let x = PI * x;
+    if x.abs() < 1e-5 { 1.0 } else { x.sin() / x }
+}
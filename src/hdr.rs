@@ -0,0 +1,76 @@
+use std::io::{self, Seek, Write};
+
+use ::exr::prelude::{AnyChannel, AnyChannels, Encoding, FlatSamples, Image as ExrImage, Layer,
+                     LayerAttributes, SmallVec, WritableImage};
+use ::image::codecs::hdr::HdrEncoder;
+use ::image::Rgb;
+use half::f16;
+
+/// Storage type of the samples in an EXR file.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ExrPrecision {
+    Half,
+    Float,
+}
+
+/// One named plane of an EXR image, stored row by row from the top left.
+pub struct Channel<'a> {
+    pub name: &'a str,
+    pub samples: Vec<f32>,
+}
+
+/// Writes any number of channels as a single EXR layer. Channels named
+/// `R`, `G`, `B` and `A` are picked up as colour by other applications.
+///
+/// # Errors
+///
+/// Propagates any error from `writer` or the encoder.
+pub fn write_exr<W: Write + Seek>(writer: W, width: u32, height: u32, channels: Vec<Channel>,
+                                  precision: ExrPrecision) -> io::Result<()> {
+    let channels = channels
+        .into_iter()
+        .map(|channel| {
+            let samples = match precision {
+                ExrPrecision::Half => FlatSamples::F16(channel.samples.into_iter().map(f16::from_f32).collect()),
+                ExrPrecision::Float => FlatSamples::F32(channel.samples),
+            };
+            AnyChannel::new(channel.name, samples)
+        })
+        .collect::<Vec<_>>();
+
+    let layer = Layer::new((width as usize, height as usize), LayerAttributes::default(),
+                           Encoding::FAST_LOSSLESS, AnyChannels::sort(SmallVec::from_vec(channels)));
+
+    ExrImage::from_layer(layer)
+        .write()
+        .to_buffered(writer)
+        .map_err(io::Error::other)
+}
+
+/// Writes linear RGB as a run-length encoded Radiance RGBE (`.hdr`) file.
+///
+/// # Errors
+///
+/// Propagates any error from `writer` or the encoder.
+pub fn write_radiance<W: Write>(writer: W, width: u32, height: u32, rgb: &[[f32; 3]]) -> io::Result<()> {
+    let pixels = rgb.iter().map(|&p| Rgb(p)).collect::<Vec<_>>();
+    HdrEncoder::new(writer)
+        .encode(&pixels, width as usize, height as usize)
+        .map_err(io::Error::other)
+}
+
+/// Writes linear RGB as a little-endian portable float map (`.pfm`), whose rows
+/// run from the bottom of the image to the top.
+///
+/// # Errors
+///
+/// Propagates any error from `writer`.
+pub fn write_pfm<W: Write>(writer: &mut W, width: u32, height: u32, rgb: &[[f32; 3]]) -> io::Result<()> {
+    write!(writer, "PF\n{width} {height}\n-1.0\n")?;
+    for row in rgb.chunks_exact(width as usize).rev() {
+        for value in row.iter().flatten() {
+            writer.write_all(&value.to_le_bytes())?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,189 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Cursor, Write};
+use std::path::Path;
+
+use ::image::codecs::png::PngEncoder;
+use ::image::{ExtendedColorType, ImageEncoder};
+use nalgebra_glm::clamp_scalar;
+
+use crate::hdr::{self, Channel, ExrPrecision};
+use crate::ppm;
+use crate::tonemap::{srgb_to_linear, ToneMapping};
+use crate::types::Color;
+
+/// A rendered frame of unclamped linear radiance, stored row by row from the
+/// top-left pixel.
+///
+/// `tone_mapping` is only applied when writing 8-bit formats; the floating
+/// point formats keep the radiance as is.
+#[derive(Clone)]
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<Color>,
+    pub tone_mapping: ToneMapping,
+    /// Number of samples behind each pixel, kept by adaptive renders and
+    /// written to EXR files as an extra `spp` channel.
+    pub sample_counts: Option<Vec<u32>>,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ImageFormat {
+    Png,
+    /// Binary P6 portable pixmap
+    Ppm,
+    /// ASCII P3 portable pixmap
+    PpmAscii,
+    /// EXR with linear R, G and B channels
+    Exr(ExrPrecision),
+    /// Radiance RGBE
+    Hdr,
+    /// Portable float map
+    Pfm,
+}
+
+impl ImageFormat {
+    /// Guesses the format from a file extension. `.ppm` maps to the binary
+    /// variant and `.exr` to half floats; the alternatives have to be asked
+    /// for explicitly.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Option<ImageFormat> {
+        let extension = path.as_ref().extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "png" => Some(ImageFormat::Png),
+            "ppm" => Some(ImageFormat::Ppm),
+            "exr" => Some(ImageFormat::Exr(ExrPrecision::Half)),
+            "hdr" => Some(ImageFormat::Hdr),
+            "pfm" => Some(ImageFormat::Pfm),
+            _ => None,
+        }
+    }
+}
+
+impl Image {
+    pub fn new(width: u32, height: u32) -> Image {
+        Image {
+            width,
+            height,
+            pixels: vec![Color::new(0.0, 0.0, 0.0); (width * height) as usize],
+            tone_mapping: ToneMapping::default(),
+            sample_counts: None,
+        }
+    }
+
+    pub fn get(&self, x: u32, y: u32) -> Color {
+        self.pixels[(y * self.width + x) as usize]
+    }
+
+    pub fn set(&mut self, x: u32, y: u32, color: Color) {
+        self.pixels[(y * self.width + x) as usize] = color;
+    }
+
+    /// Tone maps and quantises the pixels to interleaved 8-bit RGB.
+    pub fn to_rgb8(&self) -> Vec<u8> {
+        self.pixels
+            .iter()
+            .map(|&c| self.tone_mapping.apply(c))
+            .flat_map(|c| [c.x, c.y, c.z])
+            .map(|x| (256.0 * clamp_scalar(x, 0.0, 0.999)) as u8)
+            .collect()
+    }
+
+    pub fn to_rgb32f(&self) -> Vec<[f32; 3]> {
+        self.pixels.iter().map(|c| [c.x, c.y, c.z]).collect()
+    }
+
+    /// Splits the pixels into separate `R`, `G` and `B` planes, followed by
+    /// an `spp` plane if the sample counts are known.
+    pub fn channels(&self) -> Vec<Channel<'static>> {
+        let mut channels: Vec<Channel> = ["R", "G", "B"]
+            .iter()
+            .enumerate()
+            .map(|(i, &name)| Channel { name, samples: self.pixels.iter().map(|c| c[i]).collect() })
+            .collect();
+        if let Some(counts) = &self.sample_counts {
+            channels.push(Channel { name: "spp", samples: counts.iter().map(|&n| n as f32).collect() });
+        }
+        channels
+    }
+
+    /// False colour picture of the sample counts, running from black for the
+    /// fewest samples through purple and orange to pale yellow for the most.
+    pub fn sample_heatmap(&self) -> Option<Image> {
+        let counts = self.sample_counts.as_ref()?;
+        let most = counts.iter().copied().max().unwrap_or(0).max(1);
+        let mut heatmap = Image::new(self.width, self.height);
+        for (pixel, &count) in heatmap.pixels.iter_mut().zip(counts) {
+            *pixel = heat_color(count as f32 / most as f32);
+        }
+        Some(heatmap)
+    }
+
+    /// Saves the image in the format implied by the path's extension.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the extension is not a supported format or the file cannot be written.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let path = path.as_ref();
+        let format = ImageFormat::from_path(path).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput,
+                           format!("unsupported image format '{}'", path.display()))
+        })?;
+        self.save_as(path, format)
+    }
+
+    /// Saves the image in the given format.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the file cannot be created or written.
+    pub fn save_as<P: AsRef<Path>>(&self, path: P, format: ImageFormat) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write(&mut writer, format)?;
+        writer.flush()
+    }
+
+    /// Encodes the image into `writer`.
+    ///
+    /// # Errors
+    ///
+    /// Propagates any error from `writer` or the encoder.
+    pub fn write<W: Write>(&self, writer: &mut W, format: ImageFormat) -> io::Result<()> {
+        match format {
+            ImageFormat::Png => PngEncoder::new(writer)
+                .write_image(&self.to_rgb8(), self.width, self.height, ExtendedColorType::Rgb8)
+                .map_err(io::Error::other),
+            ImageFormat::Ppm => ppm::write_binary(self, writer),
+            ImageFormat::PpmAscii => ppm::write_ascii(self, writer),
+            ImageFormat::Exr(precision) => {
+                // The EXR encoder seeks back to fill in its offset tables, so
+                // the file is assembled in memory first
+                let mut buffer = Cursor::new(Vec::new());
+                hdr::write_exr(&mut buffer, self.width, self.height, self.channels(), precision)?;
+                writer.write_all(buffer.get_ref())
+            }
+            ImageFormat::Hdr => hdr::write_radiance(writer, self.width, self.height, &self.to_rgb32f()),
+            ImageFormat::Pfm => hdr::write_pfm(writer, self.width, self.height, &self.to_rgb32f()),
+        }
+    }
+}
+
+/// Stops of the heatmap's colour ramp, in sRGB.
+const HEAT_RAMP: [[f32; 3]; 5] = [
+    [0.0, 0.0, 0.02],
+    [0.34, 0.06, 0.43],
+    [0.73, 0.21, 0.33],
+    [0.98, 0.55, 0.04],
+    [0.99, 1.0, 0.64],
+];
+
+/// Linear colour of the point `t` in `[0, 1]` along the heatmap's ramp.
+fn heat_color(t: f32) -> Color {
+    let position = t.clamp(0.0, 1.0) * (HEAT_RAMP.len() - 1) as f32;
+    let stop = (position as usize).min(HEAT_RAMP.len() - 2);
+    let f = position - stop as f32;
+    let (a, b) = (HEAT_RAMP[stop], HEAT_RAMP[stop + 1]);
+    Color::new(srgb_to_linear(a[0] + f * (b[0] - a[0])),
+               srgb_to_linear(a[1] + f * (b[1] - a[1])),
+               srgb_to_linear(a[2] + f * (b[2] - a[2])))
+}
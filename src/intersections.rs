@@ -1,12 +1,9 @@
-use crate::types::{Point3, Ray, Vec3};
-use crate::material::{Material};
+use crate::types::{Onb, Point3, Ray, Vec3};
+use crate::material::{Material, Scatter};
+use crate::random::{pick, to_sphere_direction, uniform_sphere_direction};
+use std::fmt;
 use std::sync::Arc;
-use bvh::aabb::{AABB, Bounded};
-use bvh::bvh::{BVH, BVHNode};
-use bvh::nalgebra::{Point3 as BVHPoint3, Vector3 as BVHVector3};
-use bvh::ray::Ray as BVHRay;
-use bvh::bounding_hierarchy::BHShape;
-use std::cell::RefCell;
+use crate::bvh::{Aabb, Bounded, Bvh};
 use nalgebra_glm::RealField;
 
 pub struct HitRecord {
@@ -31,22 +28,45 @@ impl HitRecord {
 
 pub trait Hittable {
     fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord>;
-}
 
-pub struct WorldObject {
-    inner: Arc<dyn Hittable + Send + Sync>,
-    aabb: AABB,
-    node_index: usize,
-}
+    /// Whether the object emits light and implements `pdf_value` and `random`,
+    /// so the renderer can sample it directly.
+    fn is_light(&self) -> bool {
+        false
+    }
+
+    /// Solid angle density with which `random` produces `direction` from `origin`.
+    fn pdf_value(&self, _origin: &Point3, _direction: &Vec3) -> f32 {
+        0.0
+    }
 
-impl BHShape for WorldObject {
-    fn set_bh_node_index(&mut self, idx: usize) {
-        self.node_index = idx;
+    /// Direction from `origin` towards the point of the object placed by `u`,
+    /// a point of the unit square.
+    fn random(&self, _origin: &Point3, _u: (f32, f32)) -> Vec3 {
+        Vec3::new(1.0, 0.0, 0.0)
     }
+}
 
-    fn bh_node_index(&self) -> usize {
-        self.node_index
+/// Converts the area density of a uniformly sampled flat shape into a solid
+/// angle density as seen along `direction`.
+pub(crate) fn area_pdf(hit: &HitRecord, direction: &Vec3, area: f32) -> f32 {
+    let distance_squared = hit.t * hit.t * direction.magnitude_squared();
+    let cosine = (direction.dot(&hit.normal) / direction.magnitude()).abs();
+    if cosine < 1e-6 {
+        return 0.0;
     }
+    distance_squared / (cosine * area)
+}
+
+/// Solid angle density of `shape.random(origin, u)`, for shapes sampled uniformly by area.
+fn flat_pdf<T: Hittable>(shape: &T, origin: &Point3, direction: &Vec3, area: f32) -> f32 {
+    shape.hit(&Ray::new(*origin, *direction, 0.0), 0.001, f32::INFINITY)
+        .map_or(0.0, |hit| area_pdf(&hit, direction, area))
+}
+
+pub struct WorldObject {
+    inner: Arc<dyn Hittable + Send + Sync>,
+    aabb: Aabb,
 }
 
 impl Hittable for WorldObject {
@@ -56,65 +76,76 @@ impl Hittable for WorldObject {
 }
 
 impl Bounded for WorldObject {
-    fn aabb(&self) -> AABB {
+    fn aabb(&self) -> Aabb {
         self.aabb
     }
 }
 
-thread_local! {
-    pub static CACHED_INDICES: RefCell<Vec<usize>> = RefCell::new(vec![]);
-}
-
 pub struct World {
     pub objects: Vec<WorldObject>,
-    pub bvh: BVH,
+    pub bvh: Bvh,
+    /// Emissive objects, sampled for direct lighting.
+    pub lights: Vec<Arc<dyn Hittable + Send + Sync>>,
+}
+
+impl Default for World {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl World {
     pub fn new() -> World {
         World {
             objects: vec![],
-            bvh: BVH { nodes: vec![] },
+            bvh: Bvh::default(),
+            lights: vec![],
         }
     }
 
     pub fn build_bvh(&mut self) {
-        let bvh = BVH::build(&mut self.objects);
-        self.bvh = bvh;
+        self.bvh = Bvh::build(&mut self.objects);
     }
 
     pub fn add<T>(&mut self, obj: T) where T: Hittable + Bounded + Sync + Send + 'static {
         let aabb = obj.aabb();
+        self.add_shared(Arc::new(obj), aabb);
+    }
 
-        self.objects.push(WorldObject {
-            inner: Arc::new(obj) as Arc<dyn Hittable + Send + Sync>,
-            aabb,
-            node_index: self.objects.len(),
-        });
+    /// Adds an object that other worlds may hold as well.
+    pub(crate) fn add_shared(&mut self, inner: Arc<dyn Hittable + Send + Sync>, aabb: Aabb) {
+        if inner.is_light() {
+            self.lights.push(inner.clone());
+        }
+
+        self.objects.push(WorldObject { inner, aabb });
     }
 }
 
 impl Hittable for World {
     fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
-        let mut temp_hit = None;
-        let mut closest_t = t_max;
+        self.bvh.hit(&self.objects, ray, t_min, t_max)
+    }
 
-        let bvh_ray = BVHRay::new(BVHPoint3::new(ray.origin().x, ray.origin().y, ray.origin().z),
-                                  BVHVector3::new(ray.direction().x, ray.direction().y, ray.direction().z));
+    fn is_light(&self) -> bool {
+        !self.lights.is_empty()
+    }
 
-        CACHED_INDICES.with(|ci| {
-            let mut ci = ci.borrow_mut();
-            ci.clear();
-            BVHNode::traverse_recursive(&self.bvh.nodes, 0, &bvh_ray, &mut ci);
+    // Each light is picked with equal probability, as for the lights of a scene
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3) -> f32 {
+        let sum: f32 = self.lights.iter().map(|light| light.pdf_value(origin, direction)).sum();
+        sum / self.lights.len() as f32
+    }
 
-            for index in ci.iter() {
-                if let Some(hit) = self.objects[*index].hit(&ray, t_min, closest_t) {
-                    closest_t = hit.t;
-                    temp_hit = Some(hit);
-                }
-            }
-        });
-        temp_hit
+    fn random(&self, origin: &Point3, u: (f32, f32)) -> Vec3 {
+        let (index, rest) = pick(u.0, self.lights.len());
+        self.lights[index].random(origin, (rest, u.1))
+    }
+}
+
+impl Bounded for World {
+    fn aabb(&self) -> Aabb {
+        self.objects.iter().fold(Aabb::empty(), |aabb, object| aabb.join(&object.aabb))
     }
 }
 
@@ -183,17 +214,39 @@ impl Hittable for Sphere {
         }
         None
     }
-}
 
-impl Bounded for Sphere {
-    fn aabb(&self) -> AABB {
-        let half_size = BVHVector3::new(self.radius, self.radius, self.radius);
+    fn is_light(&self) -> bool {
+        self.material.is_emitter()
+    }
+
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3) -> f32 {
+        let distance_squared = (self.center - origin).magnitude_squared();
+        if distance_squared <= self.radius * self.radius
+            || self.hit(&Ray::new(*origin, *direction, 0.0), 0.001, f32::INFINITY).is_none() {
+            return 0.0;
+        }
 
-        let center = BVHPoint3::new(self.center.x, self.center.y, self.center.z);
+        let cos_theta_max = (1.0 - self.radius * self.radius / distance_squared).sqrt();
+        let solid_angle = 2.0 * f32::pi() * (1.0 - cos_theta_max);
+        1.0 / solid_angle
+    }
 
-        let min = center - half_size;
-        let max = center + half_size;
-        AABB::with_bounds(min, max)
+    // Samples the cone of directions subtended by the sphere; from inside the
+    // sphere there is no such cone and `pdf_value` is zero.
+    fn random(&self, origin: &Point3, u: (f32, f32)) -> Vec3 {
+        let direction = self.center - origin;
+        let distance_squared = direction.magnitude_squared();
+        if distance_squared <= self.radius * self.radius {
+            return uniform_sphere_direction(u);
+        }
+        Onb::from_w(&direction).local(&to_sphere_direction(u, self.radius, distance_squared))
+    }
+}
+
+impl Bounded for Sphere {
+    fn aabb(&self) -> Aabb {
+        let half_size = Vec3::repeat(self.radius);
+        Aabb::new(self.center - half_size, self.center + half_size)
     }
 }
 
@@ -225,7 +278,7 @@ impl Hittable for MovingSphere {
 }
 
 impl Bounded for MovingSphere {
-    fn aabb(&self) -> AABB {
+    fn aabb(&self) -> Aabb {
         let s1 = Sphere {
             center: self.center(self.time1),
             material: self.material.clone(),
@@ -240,4 +293,370 @@ impl Bounded for MovingSphere {
 
         s1.aabb().join(&s2.aabb())
     }
-}
\ No newline at end of file
+}
+
+/// Half thickness given to the bounding boxes of flat shapes, so the BVH never
+/// sees a box with zero extent along the shape's normal.
+pub(crate) const AABB_PADDING: f32 = 0.0001;
+
+/// The box between `min` and `max`, widened by `AABB_PADDING` on both sides
+/// along any axis where it would otherwise be thinner than that.
+pub(crate) fn padded_aabb(mut min: Point3, mut max: Point3) -> Aabb {
+    for axis in 0..3 {
+        if max[axis] - min[axis] < 2.0 * AABB_PADDING {
+            min[axis] -= AABB_PADDING;
+            max[axis] += AABB_PADDING;
+        }
+    }
+    Aabb::new(min, max)
+}
+
+#[derive(Clone)]
+pub struct XYRect {
+    pub x0: f32,
+    pub x1: f32,
+    pub y0: f32,
+    pub y1: f32,
+    pub k: f32,
+    pub material: Material,
+}
+
+impl Hittable for XYRect {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+        let t = (self.k - ray.origin().z) / ray.direction().z;
+        if !(t > t_min && t < t_max) {
+            return None;
+        }
+
+        let p = ray.at(t);
+        if p.x < self.x0 || p.x > self.x1 || p.y < self.y0 || p.y > self.y1 {
+            return None;
+        }
+
+        let mut result = HitRecord {
+            point: p,
+            normal: Vec3::new(0.0, 0.0, 1.0),
+            t,
+            front_face: true,
+            material: self.material.clone(),
+            u: (p.x - self.x0) / (self.x1 - self.x0),
+            v: (p.y - self.y0) / (self.y1 - self.y0),
+        };
+        result.set_face_normal(ray);
+        Some(result)
+    }
+
+    fn is_light(&self) -> bool {
+        self.material.is_emitter()
+    }
+
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3) -> f32 {
+        flat_pdf(self, origin, direction, (self.x1 - self.x0) * (self.y1 - self.y0))
+    }
+
+    fn random(&self, origin: &Point3, u: (f32, f32)) -> Vec3 {
+        let point = Point3::new(self.x0 + u.0 * (self.x1 - self.x0),
+                                 self.y0 + u.1 * (self.y1 - self.y0),
+                                 self.k);
+        point - origin
+    }
+}
+
+impl Bounded for XYRect {
+    fn aabb(&self) -> Aabb {
+        Aabb::new(Point3::new(self.x0, self.y0, self.k - AABB_PADDING), Point3::new(self.x1, self.y1, self.k + AABB_PADDING))
+    }
+}
+
+#[derive(Clone)]
+pub struct XZRect {
+    pub x0: f32,
+    pub x1: f32,
+    pub z0: f32,
+    pub z1: f32,
+    pub k: f32,
+    pub material: Material,
+}
+
+impl Hittable for XZRect {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+        let t = (self.k - ray.origin().y) / ray.direction().y;
+        if !(t > t_min && t < t_max) {
+            return None;
+        }
+
+        let p = ray.at(t);
+        if p.x < self.x0 || p.x > self.x1 || p.z < self.z0 || p.z > self.z1 {
+            return None;
+        }
+
+        let mut result = HitRecord {
+            point: p,
+            normal: Vec3::new(0.0, 1.0, 0.0),
+            t,
+            front_face: true,
+            material: self.material.clone(),
+            u: (p.x - self.x0) / (self.x1 - self.x0),
+            v: (p.z - self.z0) / (self.z1 - self.z0),
+        };
+        result.set_face_normal(ray);
+        Some(result)
+    }
+
+    fn is_light(&self) -> bool {
+        self.material.is_emitter()
+    }
+
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3) -> f32 {
+        flat_pdf(self, origin, direction, (self.x1 - self.x0) * (self.z1 - self.z0))
+    }
+
+    fn random(&self, origin: &Point3, u: (f32, f32)) -> Vec3 {
+        let point = Point3::new(self.x0 + u.0 * (self.x1 - self.x0),
+                                 self.k,
+                                 self.z0 + u.1 * (self.z1 - self.z0));
+        point - origin
+    }
+}
+
+impl Bounded for XZRect {
+    fn aabb(&self) -> Aabb {
+        Aabb::new(Point3::new(self.x0, self.k - AABB_PADDING, self.z0), Point3::new(self.x1, self.k + AABB_PADDING, self.z1))
+    }
+}
+
+#[derive(Clone)]
+pub struct YZRect {
+    pub y0: f32,
+    pub y1: f32,
+    pub z0: f32,
+    pub z1: f32,
+    pub k: f32,
+    pub material: Material,
+}
+
+impl Hittable for YZRect {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+        let t = (self.k - ray.origin().x) / ray.direction().x;
+        if !(t > t_min && t < t_max) {
+            return None;
+        }
+
+        let p = ray.at(t);
+        if p.y < self.y0 || p.y > self.y1 || p.z < self.z0 || p.z > self.z1 {
+            return None;
+        }
+
+        let mut result = HitRecord {
+            point: p,
+            normal: Vec3::new(1.0, 0.0, 0.0),
+            t,
+            front_face: true,
+            material: self.material.clone(),
+            u: (p.y - self.y0) / (self.y1 - self.y0),
+            v: (p.z - self.z0) / (self.z1 - self.z0),
+        };
+        result.set_face_normal(ray);
+        Some(result)
+    }
+
+    fn is_light(&self) -> bool {
+        self.material.is_emitter()
+    }
+
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3) -> f32 {
+        flat_pdf(self, origin, direction, (self.y1 - self.y0) * (self.z1 - self.z0))
+    }
+
+    fn random(&self, origin: &Point3, u: (f32, f32)) -> Vec3 {
+        let point = Point3::new(self.k,
+                                 self.y0 + u.0 * (self.y1 - self.y0),
+                                 self.z0 + u.1 * (self.z1 - self.z0));
+        point - origin
+    }
+}
+
+impl Bounded for YZRect {
+    fn aabb(&self) -> Aabb {
+        Aabb::new(Point3::new(self.k - AABB_PADDING, self.y0, self.z0), Point3::new(self.k + AABB_PADDING, self.y1, self.z1))
+    }
+}
+
+/// A parallelogram spanned by the edge vectors `u` and `v` from the corner `q`.
+#[derive(Clone)]
+pub struct Quad {
+    q: Point3,
+    u: Vec3,
+    v: Vec3,
+    normal: Vec3,
+    d: f32,
+    w: Vec3,
+    pub material: Material,
+}
+
+impl Quad {
+    pub fn new(q: Point3, u: Vec3, v: Vec3, material: Material) -> Quad {
+        let n = u.cross(&v);
+        let normal = n.normalize();
+        Quad {
+            q,
+            u,
+            v,
+            normal,
+            d: normal.dot(&q),
+            w: n / n.dot(&n),
+            material,
+        }
+    }
+}
+
+impl Hittable for Quad {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+        let denom = self.normal.dot(&ray.direction());
+        if denom.abs() < 1e-8 {
+            return None;
+        }
+
+        let t = (self.d - self.normal.dot(&ray.origin())) / denom;
+        if !(t > t_min && t < t_max) {
+            return None;
+        }
+
+        let p = ray.at(t);
+        let planar = p - self.q;
+        let alpha = self.w.dot(&planar.cross(&self.v));
+        let beta = self.w.dot(&self.u.cross(&planar));
+        if !(0.0..=1.0).contains(&alpha) || !(0.0..=1.0).contains(&beta) {
+            return None;
+        }
+
+        let mut result = HitRecord {
+            point: p,
+            normal: self.normal,
+            t,
+            front_face: true,
+            material: self.material.clone(),
+            u: alpha,
+            v: beta,
+        };
+        result.set_face_normal(ray);
+        Some(result)
+    }
+
+    fn is_light(&self) -> bool {
+        self.material.is_emitter()
+    }
+
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3) -> f32 {
+        flat_pdf(self, origin, direction, self.u.cross(&self.v).magnitude())
+    }
+
+    fn random(&self, origin: &Point3, u: (f32, f32)) -> Vec3 {
+        self.q + u.0 * self.u + u.1 * self.v - origin
+    }
+}
+
+impl Bounded for Quad {
+    fn aabb(&self) -> Aabb {
+        let corners = [self.q, self.q + self.u, self.q + self.v, self.q + self.u + self.v];
+        let mut min = corners[0];
+        let mut max = corners[0];
+        for corner in &corners[1..] {
+            min = min.inf(corner);
+            max = max.sup(corner);
+        }
+        padded_aabb(min, max)
+    }
+}
+
+/// Corners handed to `Cuboid::new` that do not differ along `axis`, which
+/// would leave the box flat and its sides without an area.
+#[derive(Clone, Copy, Debug)]
+pub struct FlatBoxError {
+    pub axis: char,
+    pub a: f32,
+    pub b: f32,
+}
+
+impl fmt::Display for FlatBoxError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "box corners {} and {} must differ along {}", self.a, self.b, self.axis)
+    }
+}
+
+impl std::error::Error for FlatBoxError {}
+
+/// An axis-aligned box between two opposite corners, made of six `Quad` sides.
+#[derive(Clone)]
+pub struct Cuboid {
+    min: Point3,
+    max: Point3,
+    sides: [Quad; 6],
+}
+
+impl Cuboid {
+    /// A box between the opposite corners `a` and `b`.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the corners do not differ on every axis.
+    pub fn new(a: Point3, b: Point3, material: Material) -> Result<Cuboid, FlatBoxError> {
+        for (axis, i) in [('x', 0), ('y', 1), ('z', 2)] {
+            // Also catches NaN coordinates
+            if !(a[i] < b[i] || b[i] < a[i]) {
+                return Err(FlatBoxError { axis, a: a[i], b: b[i] });
+            }
+        }
+        let min = a.inf(&b);
+        let max = a.sup(&b);
+
+        let dx = Vec3::new(max.x - min.x, 0.0, 0.0);
+        let dy = Vec3::new(0.0, max.y - min.y, 0.0);
+        let dz = Vec3::new(0.0, 0.0, max.z - min.z);
+
+        let sides = [
+            Quad::new(Point3::new(min.x, min.y, max.z), dx, dy, material.clone()),
+            Quad::new(Point3::new(max.x, min.y, max.z), -dz, dy, material.clone()),
+            Quad::new(Point3::new(max.x, min.y, min.z), -dx, dy, material.clone()),
+            Quad::new(Point3::new(min.x, min.y, min.z), dz, dy, material.clone()),
+            Quad::new(Point3::new(min.x, max.y, max.z), dx, -dz, material.clone()),
+            Quad::new(Point3::new(min.x, min.y, min.z), dx, dz, material),
+        ];
+
+        Ok(Cuboid { min, max, sides })
+    }
+}
+
+impl Hittable for Cuboid {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+        let mut temp_hit = None;
+        let mut closest_t = t_max;
+
+        for side in &self.sides {
+            if let Some(hit) = side.hit(ray, t_min, closest_t) {
+                closest_t = hit.t;
+                temp_hit = Some(hit);
+            }
+        }
+        temp_hit
+    }
+
+    fn is_light(&self) -> bool {
+        self.sides.iter().all(Hittable::is_light)
+    }
+
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3) -> f32 {
+        self.sides.iter().map(|side| side.pdf_value(origin, direction)).sum::<f32>() / 6.0
+    }
+
+    fn random(&self, origin: &Point3, u: (f32, f32)) -> Vec3 {
+        let (index, rest) = pick(u.0, self.sides.len());
+        self.sides[index].random(origin, (rest, u.1))
+    }
+}
+
+impl Bounded for Cuboid {
+    fn aabb(&self) -> Aabb {
+        padded_aabb(self.min, self.max)
+    }
+}
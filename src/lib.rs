@@ -0,0 +1,45 @@
+#![deny(clippy::pedantic)]
+#![deny(clippy::panic)]
+#![allow(clippy::cast_possible_truncation)]
+#![allow(clippy::cast_precision_loss)]
+#![allow(clippy::cast_sign_loss)]
+#![allow(clippy::must_use_candidate)]
+
+pub mod bvh;
+pub mod camera;
+pub mod environment;
+pub mod film;
+pub mod filter;
+pub mod hdr;
+pub mod image;
+pub mod intersections;
+pub mod material;
+pub mod medium;
+pub mod mesh;
+pub mod microfacet;
+pub mod obj;
+pub mod pdf;
+pub mod perlin;
+pub mod ppm;
+pub mod random;
+pub mod render;
+pub mod sampler;
+pub mod scenes;
+pub mod texture;
+pub mod tile;
+pub mod tonemap;
+pub mod transform;
+pub mod types;
+
+pub use crate::camera::Camera;
+pub use crate::environment::{Environment, EnvironmentMap};
+pub use crate::image::Image;
+pub use crate::intersections::{HitRecord, Hittable, World};
+pub use crate::material::{Material, Scatter, ScatterRecord};
+pub use crate::mesh::{Mesh, Triangle};
+pub use crate::render::{render, AdaptiveSampling, MisHeuristic, RenderSettings, Renderer};
+pub use crate::scenes::Scene;
+pub use crate::texture::{GetColor, Texture};
+pub use crate::tonemap::{ToneMapOperator, ToneMapping};
+pub use crate::transform::Transform;
+pub use crate::types::{Color, Point3, Ray, Vec3};
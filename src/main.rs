@@ -4,132 +4,118 @@
 #![allow(clippy::cast_precision_loss)]
 #![allow(clippy::cast_sign_loss)]
 
-use std::fs::OpenOptions;
-use std::io::BufWriter;
+use std::io;
 use std::path::Path;
 use std::process::Command;
 
-use indicatif::{ProgressBar, ProgressDrawTarget, ProgressStyle};
-use rayon::prelude::*;
+use clap::Parser;
 
-use crate::camera::Camera;
-use crate::intersections::{Hittable, World};
-use crate::material::Scatter;
-use crate::ppm::{WritePPM, write_header};
-use crate::random::rand;
-use crate::types::{Color, Ray, Vec3};
+use raytracer::random::Rng;
+use raytracer::image::ImageFormat;
+use raytracer::{scenes, Environment, EnvironmentMap, Image, RenderSettings, Renderer};
 
-mod camera;
-mod intersections;
-mod material;
-mod perlin;
-mod ppm;
-mod random;
-mod scenes;
-mod texture;
-mod types;
+use crate::cli::{Args, SceneSource};
 
-fn ray_color(ray: &Ray, world: &World, depth: i32) -> Color {
-    if depth <= 0 {
-        return Color::new(0.0, 0.0, 0.0);
-    }
+mod cli;
 
-    if let Some(hit) = world.hit(&ray, 0.001, f32::INFINITY) {
-        if let Some((scattered, attenuation)) = hit.material.scatter(&ray, &hit) {
-            attenuation.component_mul(&ray_color(&scattered, &world, depth - 1))
-        } else {
-            Color::new(0.0, 0.0, 0.0)
+pub fn main() {
+    let settings = match Args::parse().settings() {
+        Ok(settings) => settings,
+        Err(e) => e.exit(),
+    };
+
+    if let Some(threads) = settings.threads {
+        if let Err(e) = rayon::ThreadPoolBuilder::new().num_threads(threads).build_global() {
+            eprintln!("error: failed to start {threads} worker threads: {e}");
+            std::process::exit(1);
         }
-    } else {
-        let unit_direction = ray.direction().normalize();
-        let t = 0.5 * (unit_direction.y + 1.0);
-        (1.0 - t) * Color::new(1.0, 1.0, 1.0) + t * Color::new(0.5, 0.7, 1.0)
     }
-}
-
-pub fn main() {
-    //let scene = scenes::random_spheres::scene();
-    //let scene = scenes::two_spheres::scene();
-    let scene = scenes::two_perlin_spheres::scene();
-
-    let aspect_ratio = 16.0 / 9.0;
-
-    let focus_dist = 10.0;
-
-    let cam = Camera::new(
-        &scene.lookfrom,
-        &scene.lookat,
-        &Vec3::new(0.0, 1.0, 0.0),
-        scene.vfov,
-        aspect_ratio,
-        scene.aperture,
-        focus_dist,
-        0.0,
-        1.0,
-    );
-
-    let image_width = 500;
-    let image_height = (image_width as f32 / aspect_ratio) as u32;
-    let samples_per_pixel = 50;
-    let max_depth = 50;
-
-    let path = Path::new("target/image.ppm");
-    let mut file = BufWriter::new(
-        OpenOptions::new()
-            .create(true)
-            .write(true)
-            .open(&path)
-            .unwrap(),
-    );
-
-    write_header(&mut file, image_width, image_height);
-
-    let pb = ProgressBar::new((image_height * image_width).into());
-    pb.set_draw_target(ProgressDrawTarget::stdout());
-    pb.set_draw_delta(pb.length() / 100);
-    pb.set_style(ProgressStyle::default_bar().template(
-        "[{elapsed} elapsed] {wide_bar:.cyan/white} {percent}% [{eta} remaining] [rendering]",
-    ));
 
-    let pixels = (0..image_height)
-        .rev()
-        .flat_map(|j| (0..image_width).map(move |i| (j, i)))
-        .collect::<Vec<_>>()
-        .into_par_iter()
-        .chunks(1024)
-        .map(|chunk| {
-            chunk
-                .iter()
-                .map(|(j, i)| {
-                    let mut pixel_color: Color = (0..samples_per_pixel)
-                        .map(|_i| {
-                            let u = (*i as f32 + rand()) / (image_width - 1) as f32;
-                            let v = (*j as f32 + rand()) / (image_height - 1) as f32;
-
-                            let r = cam.get_ray(u, v);
-                            ray_color(&r, &scene.world, max_depth)
-                        })
-                        .sum();
+    let rng = &mut Rng::new(settings.seed);
+    let mut scene = match &settings.scene {
+        SceneSource::Builtin(name) => {
+            let Some(scene) = scenes::by_name(name, rng) else {
+                unreachable!("scene names are validated by the argument parser");
+            };
+            scene
+        }
+        SceneSource::File(path) => match scenes::file::load(path, rng) {
+            Ok(scene) => scene,
+            Err(e) => {
+                eprintln!("error: {}: {e}", path.display());
+                std::process::exit(1);
+            }
+        },
+    };
+
+    if let Some(environment) = &settings.environment {
+        match EnvironmentMap::open(&environment.path) {
+            Ok(map) => {
+                scene.environment = Environment::Map(map
+                    .with_rotation(environment.rotation)
+                    .with_intensity(environment.intensity));
+            }
+            Err(e) => {
+                eprintln!("error: cannot load environment map '{}': {e}", environment.path.display());
+                std::process::exit(1);
+            }
+        }
+    }
 
-                    pixel_color /= samples_per_pixel as f32;
-                    pixel_color = Color::new(
-                        pixel_color.x.sqrt(),
-                        pixel_color.y.sqrt(),
-                        pixel_color.z.sqrt(),
-                    );
-                    pb.inc(1);
-                    pixel_color
-                })
-                .collect::<Vec<_>>()
-        })
-        .flatten()
-        .collect::<Vec<_>>();
+    let renderer = Renderer::new(RenderSettings {
+        image_width: settings.image_width,
+        image_height: settings.image_height,
+        samples_per_pixel: settings.samples_per_pixel,
+        adaptive: settings.adaptive,
+        max_depth: settings.max_depth,
+        rr_min_depth: settings.rr_min_depth,
+        mis_heuristic: settings.mis_heuristic,
+        tone_mapping: settings.tone_mapping,
+        show_progress: true,
+        seed: settings.seed,
+        sampler: settings.sampler,
+        filter: settings.filter,
+        tile_size: settings.tile_size,
+        tile_order: settings.tile_order,
+    });
+
+    let image = if settings.progressive {
+        renderer.render_progressive(&scene, |image| save(image, &settings.output, settings.format))
+    } else {
+        renderer.render(&scene)
+    };
+    save(&image, &settings.output, settings.format);
+
+    if let (Some(path), Some(heatmap)) = (&settings.spp_heatmap, image.sample_heatmap()) {
+        if let Err(e) = heatmap.save(path) {
+            eprintln!("error: failed to write '{}': {e}", path.display());
+            std::process::exit(1);
+        }
+    }
 
-    pb.finish();
+    if settings.open {
+        if let Err(e) = open_in_viewer(&settings.output) {
+            eprintln!("warning: could not open '{}': {e}", settings.output.display());
+        }
+    }
+}
 
-    for pixel_color in pixels {
-        pixel_color.write_ppm(&mut file);
+fn save(image: &Image, path: &Path, format: ImageFormat) {
+    if let Err(e) = image.save_as(path, format) {
+        eprintln!("error: failed to write '{}': {e}", path.display());
+        std::process::exit(1);
     }
+}
 
-    Command::new("open").arg(path).spawn().unwrap();
+fn open_in_viewer(path: &Path) -> io::Result<()> {
+    let mut command = if cfg!(target_os = "macos") {
+        Command::new("open")
+    } else if cfg!(target_os = "windows") {
+        let mut command = Command::new("cmd");
+        command.args(["/C", "start", ""]);
+        command
+    } else {
+        Command::new("xdg-open")
+    };
+    command.arg(path).status().map(|_| ())
 }
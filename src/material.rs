@@ -1,14 +1,47 @@
 #![allow(dead_code)]
 
-use crate::types::{Ray, Color, Vec3};
+use crate::types::{Ray, Color, Point3, Vec3};
+use std::f32::consts::PI;
+use std::sync::Arc;
 use crate::intersections::HitRecord;
-use crate::random::{Vector, rand};
+use crate::microfacet;
+use crate::pdf::Pdf;
+use crate::random::uniform_sphere_direction;
+use crate::sampler::{PixelSampler, Sampler};
 use crate::texture::{Checker, GetColor, SolidColor, Texture};
+use crate::tonemap::luminance;
+use crate::types::Onb;
 use enum_dispatch::enum_dispatch;
 
+/// Outcome of a ray scattering off a surface or inside a medium.
+pub enum ScatterRecord {
+    /// A single possible outgoing ray, e.g. a mirror reflection. Light
+    /// sampling cannot reach such a lobe, so the ray is followed as is.
+    Specular { ray: Ray, attenuation: Color },
+    /// Scattering into a spread of directions. The outgoing direction is drawn
+    /// from `pdf`, and the material's `eval` says how much light it carries.
+    Sampled { pdf: Pdf },
+}
+
 #[enum_dispatch(Material)]
 pub trait Scatter {
-    fn scatter(&self, ray_in: &Ray, hit: &HitRecord) -> Option<(Ray, Color)>;
+    /// Picks how the ray continues, drawing any choices it makes from `sampler`.
+    fn scatter(&self, ray_in: &Ray, hit: &HitRecord, sampler: &mut PixelSampler) -> Option<ScatterRecord>;
+
+    /// BSDF times the cosine term: the fraction of light arriving from
+    /// `direction` that leaves back along `ray_in`. Only meaningful for
+    /// `ScatterRecord::Sampled`.
+    fn eval(&self, _ray_in: &Ray, _hit: &HitRecord, _direction: &Vec3) -> Color {
+        Color::new(0.0, 0.0, 0.0)
+    }
+
+    fn emitted(&self, _u: f32, _v: f32, _p: Point3) -> Color {
+        Color::new(0.0, 0.0, 0.0)
+    }
+
+    fn is_emitter(&self) -> bool {
+        false
+    }
 }
 
 #[enum_dispatch]
@@ -17,6 +50,10 @@ pub enum Material {
     Lambertian,
     Metal,
     Dielectric,
+    Microfacet,
+    Principled,
+    DiffuseLight,
+    Isotropic,
 }
 
 #[derive(Clone)]
@@ -33,9 +70,13 @@ impl Lambertian {
 }
 
 impl Scatter for Lambertian {
-    fn scatter(&self, ray_in: &Ray, hit: &HitRecord) -> Option<(Ray, Color)> {
-        let scatter_direction = hit.normal + Vec3::rand_unit();
-        Some((Ray::new(hit.point, scatter_direction, ray_in.time), self.0.value(hit.u, hit.v, hit.point)))
+    fn scatter(&self, _ray_in: &Ray, hit: &HitRecord, _sampler: &mut PixelSampler) -> Option<ScatterRecord> {
+        Some(ScatterRecord::Sampled { pdf: Pdf::cosine(&hit.normal) })
+    }
+
+    fn eval(&self, _ray_in: &Ray, hit: &HitRecord, direction: &Vec3) -> Color {
+        let cosine = hit.normal.dot(&direction.normalize()).max(0.0);
+        self.0.value(hit.u, hit.v, hit.point) * cosine / PI
     }
 }
 
@@ -55,16 +96,20 @@ impl Metal {
 }
 
 fn reflect(v: &Vec3, n: &Vec3) -> Vec3 {
-    v - 2.0 * v.dot(&n) * n
+    v - 2.0 * v.dot(n) * n
 }
 
 impl Scatter for Metal {
-    fn scatter(&self, ray_in: &Ray, hit: &HitRecord) -> Option<(Ray, Color)> {
+    // Fuzzed reflections are still treated as specular: the perturbation is
+    // a random offset of a single ray rather than a lobe with a known density.
+    fn scatter(&self, ray_in: &Ray, hit: &HitRecord, sampler: &mut PixelSampler) -> Option<ScatterRecord> {
         let reflected = reflect(&ray_in.direction().normalize(), &hit.normal);
 
-        let scattered = Ray::new(hit.point, reflected + self.fuzz * Vec3::rand_in_unit_sphere(), ray_in.time);
+        // A point uniformly distributed in the unit ball
+        let fuzz = uniform_sphere_direction(sampler.get_2d()) * sampler.get_1d().cbrt();
+        let scattered = Ray::new(hit.point, reflected + self.fuzz * fuzz, ray_in.time);
         if scattered.direction().dot(&hit.normal) > 0.0 {
-            Some((scattered, self.albedo))
+            Some(ScatterRecord::Specular { ray: scattered, attenuation: self.albedo })
         } else {
             None
         }
@@ -88,7 +133,7 @@ fn schlick(cosine: f32, ref_idx: f32) -> f32 {
 pub struct Dielectric(pub f32);
 
 impl Scatter for Dielectric {
-    fn scatter(&self, ray_in: &Ray, hit: &HitRecord) -> Option<(Ray, Color)> {
+    fn scatter(&self, ray_in: &Ray, hit: &HitRecord, sampler: &mut PixelSampler) -> Option<ScatterRecord> {
         let attenuation = Color::new(1.0, 1.0, 1.0);
         let etai_over_etat = if hit.front_face { 1.0 / self.0 } else { self.0 };
         let unit_direction = ray_in.direction().normalize();
@@ -96,18 +141,354 @@ impl Scatter for Dielectric {
         let cos_theta = (-unit_direction).dot(&hit.normal).min(1.0);
         let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
 
-        if etai_over_etat * sin_theta > 1.0 {
-            let reflected = reflect(&unit_direction, &hit.normal);
-            Some((Ray::new(hit.point, reflected, ray_in.time), attenuation))
+        let choice = sampler.get_1d();
+        let direction = if etai_over_etat * sin_theta > 1.0 || choice < schlick(cos_theta, etai_over_etat) {
+            reflect(&unit_direction, &hit.normal)
         } else {
-            let reflect_probability = schlick(cos_theta, etai_over_etat);
-            if rand() < reflect_probability {
-                let reflected = reflect(&unit_direction, &hit.normal);
-                Some((Ray::new(hit.point, reflected, ray_in.time), attenuation))
-            } else {
-                let refracted = refract(&unit_direction, &hit.normal, etai_over_etat);
-                Some((Ray::new(hit.point, refracted, ray_in.time), attenuation))
+            refract(&unit_direction, &hit.normal, etai_over_etat)
+        };
+        Some(ScatterRecord::Specular { ray: Ray::new(hit.point, direction, ray_in.time), attenuation })
+    }
+}
+
+/// How much light a microfacet surface reflects at each angle.
+#[derive(Clone, Copy, Debug)]
+pub enum Fresnel {
+    /// A metal with the complex index of refraction `eta + i k`, per colour channel.
+    Conductor { eta: Color, k: Color },
+    /// A dielectric coating with index of refraction `ior` over a diffuse
+    /// base, such as plastic or varnished wood.
+    Dielectric { ior: f32 },
+}
+
+impl Fresnel {
+    /// Names accepted by `metal`.
+    pub const METALS: &'static [&'static str] = &["aluminium", "copper", "gold", "silver"];
+
+    /// Optical constants of a common metal, sampled at red, green and blue wavelengths.
+    pub fn metal(name: &str) -> Option<Fresnel> {
+        let (eta, k) = match name {
+            "aluminium" => ([1.657, 0.880, 0.521], [9.224, 6.270, 4.837]),
+            "copper" => ([0.200, 0.924, 1.102], [3.912, 2.452, 2.142]),
+            "gold" => ([0.143, 0.374, 1.442], [3.983, 2.385, 1.603]),
+            "silver" => ([0.155, 0.117, 0.138], [4.828, 3.122, 2.147]),
+            _ => return None,
+        };
+        Some(Fresnel::Conductor {
+            eta: Color::new(eta[0], eta[1], eta[2]),
+            k: Color::new(k[0], k[1], k[2]),
+        })
+    }
+}
+
+/// Rough surface made of perfectly specular microfacets oriented after the
+/// GGX distribution, with Smith masking-shadowing between them.
+#[derive(Clone)]
+pub struct Microfacet {
+    /// Tint of a conductor's reflection, or the diffuse base colour under a
+    /// dielectric coating.
+    pub color: Texture,
+    /// Perceptual roughness between 0 (polished) and 1, taken from the
+    /// texture's luminance. The GGX width is its square.
+    pub roughness: Texture,
+    pub fresnel: Fresnel,
+}
+
+impl Microfacet {
+    pub fn conductor(fresnel: Fresnel, roughness: Texture) -> Self {
+        Microfacet { color: Texture::from(SolidColor(Color::new(1.0, 1.0, 1.0))), roughness, fresnel }
+    }
+
+    pub fn plastic(color: Texture, ior: f32, roughness: Texture) -> Self {
+        Microfacet { color, roughness, fresnel: Fresnel::Dielectric { ior } }
+    }
+
+    fn alpha(&self, hit: &HitRecord) -> f32 {
+        let roughness = luminance(self.roughness.value(hit.u, hit.v, hit.point)).clamp(0.0, 1.0);
+        // Widths below this make the distribution too peaked for f32
+        (roughness * roughness).max(1e-3)
+    }
+
+    fn shading_frame(ray_in: &Ray, hit: &HitRecord) -> (Onb, Vec3) {
+        let frame = Onb::from_w(&hit.normal);
+        let wo = frame.to_local(&-ray_in.direction().normalize());
+        (frame, wo)
+    }
+}
+
+impl Scatter for Microfacet {
+    fn scatter(&self, ray_in: &Ray, hit: &HitRecord, _sampler: &mut PixelSampler) -> Option<ScatterRecord> {
+        let (frame, wo) = Self::shading_frame(ray_in, hit);
+        if wo.z <= 0.0 {
+            return None;
+        }
+
+        let specular_probability = match self.fresnel {
+            Fresnel::Conductor { .. } => 1.0,
+            Fresnel::Dielectric { ior } => {
+                // Split samples by the rough share of energy in each lobe
+                let specular = microfacet::fresnel_dielectric(wo.z, ior);
+                let diffuse = (1.0 - specular) * luminance(self.color.value(hit.u, hit.v, hit.point));
+                if specular + diffuse > 0.0 { (specular / (specular + diffuse)).clamp(0.1, 0.9) } else { 1.0 }
             }
+        };
+
+        Some(ScatterRecord::Sampled {
+            pdf: Pdf::Glossy { frame, wo, alpha: self.alpha(hit), specular_probability },
+        })
+    }
+
+    fn eval(&self, ray_in: &Ray, hit: &HitRecord, direction: &Vec3) -> Color {
+        let (frame, wo) = Self::shading_frame(ray_in, hit);
+        let wi = frame.to_local(&direction.normalize());
+        if wo.z <= 0.0 || wi.z <= 0.0 {
+            return Color::new(0.0, 0.0, 0.0);
+        }
+
+        let alpha = self.alpha(hit);
+        let h = (wo + wi).normalize();
+        // Cook-Torrance: D G F / (4 cos_o cos_i), times cos_i
+        let specular = microfacet::d(&h, alpha) * microfacet::g2(&wo, &wi, alpha) / (4.0 * wo.z);
+        let color = self.color.value(hit.u, hit.v, hit.point);
+
+        match self.fresnel {
+            Fresnel::Conductor { eta, k } => {
+                color.component_mul(&microfacet::fresnel_conductor(wo.dot(&h), &eta, &k)) * specular
+            }
+            Fresnel::Dielectric { ior } => {
+                let coating = microfacet::fresnel_dielectric(wo.dot(&h), ior) * specular;
+                let base = color * (1.0 - microfacet::fresnel_dielectric(wo.z, ior)) * wi.z / PI;
+                base.add_scalar(coating)
+            }
+        }
+    }
+}
+
+/// Width of the GGX distribution of a clear coat's microfacets.
+const CLEARCOAT_ALPHA: f32 = 0.01;
+/// Index of refraction of a clear coat.
+const CLEARCOAT_IOR: f32 = 1.5;
+
+/// One material for most surfaces, after Disney's principled BRDF. A diffuse
+/// base under a GGX specular layer blends into a metal as `metallic` rises.
+/// Sheen, a clear coat, smooth glass transmission and emission are optional.
+///
+/// The scalar parameters lie between 0 and 1 and come from the luminance of
+/// their textures.
+#[derive(Clone)]
+pub struct Principled(Arc<PrincipledParameters>);
+
+/// Inputs of a `Principled` material.
+#[derive(Clone)]
+pub struct PrincipledParameters {
+    pub base_color: Texture,
+    pub metallic: Texture,
+    /// Perceptual roughness of the specular layer. The GGX width is its square.
+    pub roughness: Texture,
+    /// Strength of the non-metallic reflection. 0.5 matches an index of refraction of 1.5.
+    pub specular: Texture,
+    /// Strength of a polished transparent layer on top.
+    pub clearcoat: Texture,
+    /// Soft reflection at grazing angles, as seen on cloth.
+    pub sheen: Texture,
+    /// Share of the non-metallic part that is clear glass tinted by the base colour.
+    pub transmission: Texture,
+    pub emission: Option<Texture>,
+}
+
+/// Parameters of a `Principled` material at one point.
+struct PrincipledPoint {
+    base_color: Color,
+    metallic: f32,
+    alpha: f32,
+    /// Reflectance of the non-metallic specular layer at normal incidence.
+    f0: f32,
+    clearcoat: f32,
+    sheen: f32,
+    transmission: f32,
+}
+
+impl PrincipledPoint {
+    /// Index of refraction giving the specular layer's reflectance.
+    fn ior(&self) -> f32 {
+        let r = self.f0.sqrt();
+        (1.0 + r) / (1.0 - r)
+    }
+
+    fn specular_color(&self) -> Color {
+        Color::new(self.f0, self.f0, self.f0).lerp(&self.base_color, self.metallic)
+    }
+
+    /// Light that gets through the clear coat when looking along `wo`.
+    fn coat_transmittance(&self, wo: &Vec3) -> f32 {
+        1.0 - self.clearcoat * microfacet::fresnel_dielectric(wo.z, CLEARCOAT_IOR)
+    }
+
+    /// Light that gets through the specular layer to the diffuse base.
+    fn base_transmittance(&self, wo: &Vec3) -> f32 {
+        (1.0 - self.metallic) * (1.0 - microfacet::fresnel_dielectric(wo.z, self.ior()))
+    }
+}
+
+impl Default for PrincipledParameters {
+    fn default() -> Self {
+        let constant = |value| Texture::from(SolidColor(Color::new(value, value, value)));
+        PrincipledParameters {
+            base_color: constant(0.8),
+            metallic: constant(0.0),
+            roughness: constant(0.5),
+            specular: constant(0.5),
+            clearcoat: constant(0.0),
+            sheen: constant(0.0),
+            transmission: constant(0.0),
+            emission: None,
+        }
+    }
+}
+
+impl Principled {
+    pub fn new(parameters: PrincipledParameters) -> Self {
+        Principled(Arc::new(parameters))
+    }
+
+    fn at(&self, hit: &HitRecord) -> PrincipledPoint {
+        let parameters = &self.0;
+        let scalar = |texture: &Texture| luminance(texture.value(hit.u, hit.v, hit.point)).clamp(0.0, 1.0);
+        let roughness = scalar(&parameters.roughness);
+        let metallic = scalar(&parameters.metallic);
+        PrincipledPoint {
+            base_color: parameters.base_color.value(hit.u, hit.v, hit.point),
+            metallic,
+            alpha: (roughness * roughness).max(1e-3),
+            f0: 0.08 * scalar(&parameters.specular),
+            clearcoat: scalar(&parameters.clearcoat),
+            sheen: scalar(&parameters.sheen),
+            transmission: scalar(&parameters.transmission) * (1.0 - metallic),
+        }
+    }
+
+    /// Smooth refraction through the glass part, or reflection off it.
+    fn transmit(ray_in: &Ray, hit: &HitRecord, point: &PrincipledPoint, sampler: &mut PixelSampler) -> ScatterRecord {
+        let ior = point.ior();
+        let etai_over_etat = if hit.front_face { 1.0 / ior } else { ior };
+        let unit_direction = ray_in.direction().normalize();
+        let cos_theta = (-unit_direction).dot(&hit.normal).min(1.0);
+
+        let (direction, attenuation) = if sampler.get_1d() < microfacet::fresnel_dielectric(cos_theta, 1.0 / etai_over_etat) {
+            (reflect(&unit_direction, &hit.normal), Color::new(1.0, 1.0, 1.0))
+        } else {
+            (refract(&unit_direction, &hit.normal, etai_over_etat), point.base_color)
+        };
+        ScatterRecord::Specular { ray: Ray::new(hit.point, direction, ray_in.time), attenuation }
+    }
+}
+
+impl Scatter for Principled {
+    fn scatter(&self, ray_in: &Ray, hit: &HitRecord, sampler: &mut PixelSampler) -> Option<ScatterRecord> {
+        let point = self.at(hit);
+        // The glass part is picked as often as it contributes, which lets
+        // `eval` describe the opaque part alone.
+        if sampler.get_1d() < point.transmission {
+            return Some(Self::transmit(ray_in, hit, &point, sampler));
+        }
+
+        let (frame, wo) = Microfacet::shading_frame(ray_in, hit);
+        if wo.z <= 0.0 {
+            return None;
+        }
+
+        // Split samples by the share of energy each lobe reflects
+        let coat_transmittance = point.coat_transmittance(&wo);
+        let specular = coat_transmittance * luminance(microfacet::fresnel_schlick(wo.z, &point.specular_color()));
+        let diffuse = coat_transmittance * point.base_transmittance(&wo) * luminance(point.base_color)
+            + (1.0 - point.metallic) * point.sheen;
+        let coat = 1.0 - coat_transmittance;
+        let total = specular + diffuse + coat;
+        if total <= 0.0 {
+            return None;
+        }
+
+        Some(ScatterRecord::Sampled {
+            pdf: Pdf::Coated {
+                frame,
+                wo,
+                alpha: point.alpha,
+                coat_alpha: CLEARCOAT_ALPHA,
+                specular_probability: specular / total,
+                coat_probability: coat / total,
+            },
+        })
+    }
+
+    fn eval(&self, ray_in: &Ray, hit: &HitRecord, direction: &Vec3) -> Color {
+        let (frame, wo) = Microfacet::shading_frame(ray_in, hit);
+        let wi = frame.to_local(&direction.normalize());
+        if wo.z <= 0.0 || wi.z <= 0.0 {
+            return Color::new(0.0, 0.0, 0.0);
         }
+
+        let point = self.at(hit);
+        let h = (wo + wi).normalize();
+        let cos_d = wo.dot(&h);
+
+        let specular = microfacet::fresnel_schlick(cos_d, &point.specular_color())
+            * (microfacet::d(&h, point.alpha) * microfacet::g2(&wo, &wi, point.alpha) / (4.0 * wo.z));
+        let diffuse = point.base_color * (point.base_transmittance(&wo) * wi.z / PI);
+        let sheen = (1.0 - point.metallic) * point.sheen * (1.0 - cos_d).clamp(0.0, 1.0).powi(5) * wi.z;
+        let coat = point.clearcoat
+            * microfacet::fresnel_dielectric(cos_d, CLEARCOAT_IOR)
+            * microfacet::d(&h, CLEARCOAT_ALPHA)
+            * microfacet::g2(&wo, &wi, CLEARCOAT_ALPHA)
+            / (4.0 * wo.z);
+
+        ((specular + diffuse).add_scalar(sheen) * point.coat_transmittance(&wo)).add_scalar(coat)
+    }
+
+    fn emitted(&self, u: f32, v: f32, p: Point3) -> Color {
+        match &self.0.emission {
+            Some(emission) => emission.value(u, v, p),
+            None => Color::new(0.0, 0.0, 0.0),
+        }
+    }
+
+    fn is_emitter(&self) -> bool {
+        self.0.emission.is_some()
     }
-}
\ No newline at end of file
+}
+
+#[derive(Clone)]
+pub struct DiffuseLight(pub Texture);
+
+impl DiffuseLight {
+    pub fn from_color(color: Color) -> Self {
+        DiffuseLight(Texture::from(SolidColor(color)))
+    }
+}
+
+impl Scatter for DiffuseLight {
+    fn scatter(&self, _ray_in: &Ray, _hit: &HitRecord, _sampler: &mut PixelSampler) -> Option<ScatterRecord> {
+        None
+    }
+
+    fn emitted(&self, u: f32, v: f32, p: Point3) -> Color {
+        self.0.value(u, v, p)
+    }
+
+    fn is_emitter(&self) -> bool {
+        true
+    }
+}
+
+/// Phase function of a participating medium: scatters uniformly in all directions.
+#[derive(Clone)]
+pub struct Isotropic(pub Texture);
+
+impl Scatter for Isotropic {
+    fn scatter(&self, _ray_in: &Ray, _hit: &HitRecord, _sampler: &mut PixelSampler) -> Option<ScatterRecord> {
+        Some(ScatterRecord::Sampled { pdf: Pdf::Uniform })
+    }
+
+    fn eval(&self, _ray_in: &Ray, hit: &HitRecord, _direction: &Vec3) -> Color {
+        self.0.value(hit.u, hit.v, hit.point) / (4.0 * PI)
+    }
+}
@@ -0,0 +1,108 @@
+use std::sync::Arc;
+
+use crate::bvh::{Aabb, Bounded};
+use crate::intersections::{HitRecord, Hittable};
+use crate::material::{Isotropic, Material};
+use crate::random::mix;
+use crate::sampler::unit;
+use crate::texture::Texture;
+use crate::types::{Ray, Vec3};
+
+/// A volume of uniform density filling a closed boundary shape, such as smoke
+/// or fog. Rays passing through scatter at an exponentially distributed depth,
+/// placed by their medium sample; rays without one pass straight through.
+#[derive(Clone)]
+pub struct ConstantMedium {
+    boundary: Arc<dyn Hittable + Send + Sync>,
+    boundary_aabb: Aabb,
+    neg_inv_density: f32,
+    phase_function: Material,
+}
+
+impl ConstantMedium {
+    pub fn new<T>(boundary: T, density: f32, albedo: Texture) -> ConstantMedium
+        where T: Hittable + Bounded + Send + Sync + 'static {
+        ConstantMedium {
+            boundary_aabb: boundary.aabb(),
+            boundary: Arc::new(boundary),
+            neg_inv_density: -1.0 / density,
+            phase_function: Material::from(Isotropic(albedo)),
+        }
+    }
+}
+
+impl Hittable for ConstantMedium {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+        let entry = self.boundary.hit(ray, f32::NEG_INFINITY, f32::INFINITY)?;
+        let exit = self.boundary.hit(ray, entry.t + 0.0001, f32::INFINITY)?;
+
+        let t_enter = entry.t.max(t_min).max(0.0);
+        let t_exit = exit.t.min(t_max);
+        if t_enter >= t_exit {
+            return None;
+        }
+
+        let ray_length = ray.direction().magnitude();
+        let distance_inside_boundary = (t_exit - t_enter) * ray_length;
+        // One sample serves every medium along the ray, so each crossing
+        // hashes it with its entry distance to draw an independent depth.
+        let sample = u64::from(ray.medium_sample?.to_bits()) << 32 | u64::from(t_enter.to_bits());
+        let u = unit(mix(sample) as u32);
+        let hit_distance = self.neg_inv_density * (1.0 - u).ln();
+        if hit_distance > distance_inside_boundary {
+            return None;
+        }
+
+        let t = t_enter + hit_distance / ray_length;
+        Some(HitRecord {
+            point: ray.at(t),
+            // The normal and facing are meaningless inside a volume
+            normal: Vec3::new(1.0, 0.0, 0.0),
+            t,
+            front_face: true,
+            material: self.phase_function.clone(),
+            u: 0.0,
+            v: 0.0,
+        })
+    }
+}
+
+impl Bounded for ConstantMedium {
+    fn aabb(&self) -> Aabb {
+        self.boundary_aabb
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intersections::{Cuboid, World};
+    use crate::texture::SolidColor;
+    use crate::types::{Color, Point3};
+
+    fn slab(x: f32) -> ConstantMedium {
+        let black = Material::from(Isotropic(Texture::from(SolidColor(Color::new(0.0, 0.0, 0.0)))));
+        let boundary = Cuboid::new(Point3::new(x, -1.0, -1.0), Point3::new(x + 1.0, 1.0, 1.0), black).expect("the slab is not flat");
+        ConstantMedium::new(boundary, 0.5, Texture::from(SolidColor(Color::new(0.0, 0.0, 0.0))))
+    }
+
+    #[test]
+    fn transmittance_multiplies_across_media() {
+        let mut world = World::new();
+        world.add(slab(0.0));
+        world.add(slab(2.0));
+        world.build_bvh();
+
+        let n = 100_000;
+        let passed = (0..n)
+            .filter(|&i| {
+                let ray = Ray::new(Point3::new(-1.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0), 0.0)
+                    .with_medium_sample((i as f32 + 0.5) / n as f32);
+                world.hit(&ray, 0.001, f32::INFINITY).is_none()
+            })
+            .count();
+        let transmittance = passed as f32 / n as f32;
+        let expected = (-0.5f32 * 2.0).exp();
+        assert!((transmittance - expected).abs() < 0.01, "transmittance {} instead of {}", transmittance, expected);
+    }
+}
@@ -0,0 +1,148 @@
+use std::sync::Arc;
+
+use crate::bvh::{Aabb, Bounded};
+use crate::intersections::{area_pdf, HitRecord, Hittable, World, padded_aabb};
+use crate::material::{Material, Scatter};
+use crate::types::{Point3, Ray, Vec3};
+
+/// Vertex and index buffers shared by all triangles of one mesh.
+///
+/// `normals` and `uvs` are either empty or hold one entry per position.
+pub struct Mesh {
+    pub positions: Vec<Point3>,
+    pub normals: Vec<Vec3>,
+    pub uvs: Vec<(f32, f32)>,
+    pub indices: Vec<[u32; 3]>,
+    pub material: Material,
+}
+
+impl Mesh {
+    pub fn triangles(self: &Arc<Self>) -> impl Iterator<Item = Triangle> + '_ {
+        (0..self.indices.len()).map(move |index| Triangle { mesh: self.clone(), index })
+    }
+
+    fn vertices(&self, index: usize) -> [usize; 3] {
+        let [a, b, c] = self.indices[index];
+        [a as usize, b as usize, c as usize]
+    }
+}
+
+impl World {
+    pub fn add_mesh(&mut self, mesh: &Arc<Mesh>) {
+        for triangle in mesh.triangles() {
+            self.add(triangle);
+        }
+    }
+
+    /// A world holding only `mesh`, ready to be placed many times with `add_instance`.
+    pub fn from_mesh(mesh: Mesh) -> World {
+        let mut world = World::new();
+        world.add_mesh(&Arc::new(mesh));
+        world.build_bvh();
+        world
+    }
+}
+
+#[derive(Clone)]
+pub struct Triangle {
+    mesh: Arc<Mesh>,
+    index: usize,
+}
+
+impl Hittable for Triangle {
+    // Möller–Trumbore ray/triangle intersection
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+        let [i0, i1, i2] = self.mesh.vertices(self.index);
+        let p0 = self.mesh.positions[i0];
+        let edge1 = self.mesh.positions[i1] - p0;
+        let edge2 = self.mesh.positions[i2] - p0;
+
+        let pvec = ray.direction().cross(&edge2);
+        let det = edge1.dot(&pvec);
+        if det.abs() < 1e-8 {
+            return None;
+        }
+        let inv_det = 1.0 / det;
+
+        let tvec = ray.origin() - p0;
+        let b1 = tvec.dot(&pvec) * inv_det;
+        if !(0.0..=1.0).contains(&b1) {
+            return None;
+        }
+
+        let qvec = tvec.cross(&edge1);
+        let b2 = ray.direction().dot(&qvec) * inv_det;
+        if b2 < 0.0 || b1 + b2 > 1.0 {
+            return None;
+        }
+
+        let t = edge2.dot(&qvec) * inv_det;
+        if !(t > t_min && t < t_max) {
+            return None;
+        }
+
+        let b0 = 1.0 - b1 - b2;
+        let geometric_normal = edge1.cross(&edge2).normalize();
+        let front_face = ray.direction().dot(&geometric_normal) < 0.0;
+
+        let mut normal = if self.mesh.normals.is_empty() {
+            geometric_normal
+        } else {
+            let n = &self.mesh.normals;
+            (b0 * n[i0] + b1 * n[i1] + b2 * n[i2]).normalize()
+        };
+        if !front_face {
+            normal = -normal;
+        }
+
+        let (u, v) = if self.mesh.uvs.is_empty() {
+            (b1, b2)
+        } else {
+            let uv = &self.mesh.uvs;
+            (b0 * uv[i0].0 + b1 * uv[i1].0 + b2 * uv[i2].0,
+             b0 * uv[i0].1 + b1 * uv[i1].1 + b2 * uv[i2].1)
+        };
+
+        Some(HitRecord {
+            point: ray.at(t),
+            normal,
+            t,
+            front_face,
+            material: self.mesh.material.clone(),
+            u,
+            v,
+        })
+    }
+
+    fn is_light(&self) -> bool {
+        self.mesh.material.is_emitter()
+    }
+
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3) -> f32 {
+        let [i0, i1, i2] = self.mesh.vertices(self.index);
+        let p = &self.mesh.positions;
+        let area = 0.5 * (p[i1] - p[i0]).cross(&(p[i2] - p[i0])).magnitude();
+        self.hit(&Ray::new(*origin, *direction, 0.0), 0.001, f32::INFINITY)
+            .map_or(0.0, |hit| area_pdf(&hit, direction, area))
+    }
+
+    fn random(&self, origin: &Point3, u: (f32, f32)) -> Vec3 {
+        let [i0, i1, i2] = self.mesh.vertices(self.index);
+        let p = &self.mesh.positions;
+        // Folding the unit square onto the triangle keeps the area density uniform
+        let (mut b1, mut b2) = u;
+        if b1 + b2 > 1.0 {
+            b1 = 1.0 - b1;
+            b2 = 1.0 - b2;
+        }
+        p[i0] + b1 * (p[i1] - p[i0]) + b2 * (p[i2] - p[i0]) - origin
+    }
+}
+
+impl Bounded for Triangle {
+    fn aabb(&self) -> Aabb {
+        let [i0, i1, i2] = self.mesh.vertices(self.index);
+        let p = &self.mesh.positions;
+        padded_aabb(p[i0].inf(&p[i1]).inf(&p[i2]), p[i0].sup(&p[i1]).sup(&p[i2]))
+    }
+}
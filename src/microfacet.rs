@@ -0,0 +1,103 @@
+//! GGX (Trowbridge-Reitz) microfacet distribution and Fresnel terms.
+//!
+//! Directions are given in a local shading frame with the normal along `+z`,
+//! pointing away from the surface.
+
+use std::f32::consts::PI;
+
+use crate::types::{Color, Vec3};
+
+/// Normal distribution function: density of microfacets facing along `h`.
+pub fn d(h: &Vec3, alpha: f32) -> f32 {
+    let alpha2 = alpha * alpha;
+    let t = h.z * h.z * (alpha2 - 1.0) + 1.0;
+    alpha2 / (PI * t * t)
+}
+
+/// Smith's auxiliary function for the GGX distribution.
+fn lambda(w: &Vec3, alpha: f32) -> f32 {
+    let cos2 = w.z * w.z;
+    let tan2 = (1.0 - cos2).max(0.0) / cos2;
+    ((1.0 + alpha * alpha * tan2).sqrt() - 1.0) / 2.0
+}
+
+/// Fraction of microfacets facing along the normal that are visible from `w`.
+pub fn g1(w: &Vec3, alpha: f32) -> f32 {
+    1.0 / (1.0 + lambda(w, alpha))
+}
+
+/// Height-correlated Smith masking-shadowing for a pair of directions.
+pub fn g2(wo: &Vec3, wi: &Vec3, alpha: f32) -> f32 {
+    1.0 / (1.0 + lambda(wo, alpha) + lambda(wi, alpha))
+}
+
+/// Samples a microfacet normal from the distribution of normals visible from
+/// `wo` (Heitz 2018, "Sampling the GGX Distribution of Visible Normals").
+pub fn sample_visible_normal(wo: &Vec3, alpha: f32, u1: f32, u2: f32) -> Vec3 {
+    // Stretch the view direction into the configuration of a unit hemisphere
+    let vh = Vec3::new(alpha * wo.x, alpha * wo.y, wo.z).normalize();
+
+    let len2 = vh.x * vh.x + vh.y * vh.y;
+    let t1 = if len2 > 0.0 { Vec3::new(-vh.y, vh.x, 0.0) / len2.sqrt() } else { Vec3::new(1.0, 0.0, 0.0) };
+    let t2 = vh.cross(&t1);
+
+    // Sample the projected area of the hemisphere seen from `vh`
+    let r = u1.sqrt();
+    let phi = 2.0 * PI * u2;
+    let p1 = r * phi.cos();
+    let s = 0.5 * (1.0 + vh.z);
+    let p2 = (1.0 - s) * (1.0 - p1 * p1).sqrt() + s * r * phi.sin();
+    let nh = p1 * t1 + p2 * t2 + (1.0 - p1 * p1 - p2 * p2).max(0.0).sqrt() * vh;
+
+    Vec3::new(alpha * nh.x, alpha * nh.y, nh.z.max(0.0)).normalize()
+}
+
+/// Solid angle density of reflecting `wo` about a visible normal `h` sampled
+/// by `sample_visible_normal`.
+pub fn visible_reflection_pdf(wo: &Vec3, h: &Vec3, alpha: f32) -> f32 {
+    g1(wo, alpha) * d(h, alpha) / (4.0 * wo.z)
+}
+
+/// Unpolarised reflectance of a dielectric interface, with `eta` the ratio of
+/// the indices of refraction on the far and near sides.
+pub fn fresnel_dielectric(cos_i: f32, eta: f32) -> f32 {
+    let sin2_t = (1.0 - cos_i * cos_i) / (eta * eta);
+    if sin2_t >= 1.0 {
+        return 1.0;
+    }
+    let cos_t = (1.0 - sin2_t).sqrt();
+
+    let rs = (cos_i - eta * cos_t) / (cos_i + eta * cos_t);
+    let rp = (eta * cos_i - cos_t) / (eta * cos_i + cos_t);
+    0.5 * (rs * rs + rp * rp)
+}
+
+/// Schlick's approximation of the reflectance rising from `f0` at normal
+/// incidence to white at grazing angles.
+pub fn fresnel_schlick(cos_i: f32, f0: &Color) -> Color {
+    let weight = (1.0 - cos_i).clamp(0.0, 1.0).powi(5);
+    f0 + (Color::new(1.0, 1.0, 1.0) - f0) * weight
+}
+
+/// Unpolarised reflectance of a conductor with complex index of refraction
+/// `eta + i k`, evaluated per colour channel.
+pub fn fresnel_conductor(cos_i: f32, eta: &Color, k: &Color) -> Color {
+    let cos2 = cos_i * cos_i;
+    let sin2 = 1.0 - cos2;
+
+    Color::from_fn(|c, _| {
+        let (eta2, k2) = (eta[c] * eta[c], k[c] * k[c]);
+        let t0 = eta2 - k2 - sin2;
+        let a2_plus_b2 = (t0 * t0 + 4.0 * eta2 * k2).sqrt();
+        let a = (0.5 * (a2_plus_b2 + t0)).max(0.0).sqrt();
+
+        let t1 = a2_plus_b2 + cos2;
+        let t2 = 2.0 * cos_i * a;
+        let rs = (t1 - t2) / (t1 + t2);
+
+        let t3 = cos2 * a2_plus_b2 + sin2 * sin2;
+        let t4 = t2 * sin2;
+        let rp = rs * (t3 - t4) / (t3 + t4);
+        0.5 * (rp + rs)
+    })
+}
@@ -0,0 +1,209 @@
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::path::Path;
+
+use crate::material::Material;
+use crate::mesh::Mesh;
+use crate::types::{Point3, Vec3};
+
+#[derive(Debug)]
+pub enum ObjError {
+    Io(io::Error),
+    Parse { line: usize, message: String },
+    NoFaces,
+}
+
+impl fmt::Display for ObjError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ObjError::Io(e) => write!(f, "{e}"),
+            ObjError::Parse { line, message } => write!(f, "line {line}: {message}"),
+            ObjError::NoFaces => write!(f, "file contains no faces"),
+        }
+    }
+}
+
+impl std::error::Error for ObjError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ObjError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for ObjError {
+    fn from(e: io::Error) -> Self {
+        ObjError::Io(e)
+    }
+}
+
+/// Loads the polygons of a Wavefront OBJ file into a single triangle mesh.
+///
+/// # Errors
+///
+/// Fails if the file cannot be read, if any line is malformed or references a
+/// missing vertex, or if the file has no faces at all.
+pub fn load<P: AsRef<Path>>(path: P, material: Material) -> Result<Mesh, ObjError> {
+    parse(BufReader::new(File::open(path)?), material)
+}
+
+/// Parses OBJ data from `reader`. Faces with more than three vertices are
+/// triangulated as fans; groups, objects and material statements are ignored.
+///
+/// # Errors
+///
+/// See [`load`].
+pub fn parse<R: BufRead>(reader: R, material: Material) -> Result<Mesh, ObjError> {
+    let mut parser = Parser::default();
+
+    for (number, line) in reader.lines().enumerate() {
+        let line = line?;
+        parser.parse_line(&line).map_err(|message| ObjError::Parse { line: number + 1, message })?;
+    }
+
+    parser.finish(material)
+}
+
+type VertexKey = (usize, Option<usize>, Option<usize>);
+
+#[derive(Default)]
+struct Parser {
+    positions: Vec<Point3>,
+    uvs: Vec<(f32, f32)>,
+    normals: Vec<Vec3>,
+    vertices: Vec<VertexKey>,
+    vertex_indices: HashMap<VertexKey, u32>,
+    indices: Vec<[u32; 3]>,
+}
+
+impl Parser {
+    fn parse_line(&mut self, line: &str) -> Result<(), String> {
+        let line = line.split('#').next().unwrap_or("");
+        let mut tokens = line.split_whitespace();
+        let Some(keyword) = tokens.next() else {
+            return Ok(());
+        };
+        let args = tokens.collect::<Vec<_>>();
+
+        match keyword {
+            "v" => {
+                let [x, y, z] = parse_floats::<3>(keyword, &args, 3, 4)?;
+                self.positions.push(Point3::new(x, y, z));
+            }
+            "vt" => {
+                let [u, v] = parse_floats::<2>(keyword, &args, 1, 3)?;
+                self.uvs.push((u, v));
+            }
+            "vn" => {
+                let [x, y, z] = parse_floats::<3>(keyword, &args, 3, 3)?;
+                let normal = Vec3::new(x, y, z);
+                let length = normal.magnitude();
+                if !(length > 0.0 && length.is_finite()) {
+                    return Err(format!("normal ({x}, {y}, {z}) must have a finite, non-zero length"));
+                }
+                self.normals.push(normal.normalize());
+            }
+            "f" => self.parse_face(&args)?,
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn parse_face(&mut self, args: &[&str]) -> Result<(), String> {
+        if args.len() < 3 {
+            return Err(format!("face needs at least 3 vertices, found {}", args.len()));
+        }
+
+        let corners = args.iter().map(|arg| self.face_vertex(arg)).collect::<Result<Vec<_>, _>>()?;
+        for i in 1..corners.len() - 1 {
+            self.indices.push([corners[0], corners[i], corners[i + 1]]);
+        }
+        Ok(())
+    }
+
+    fn face_vertex(&mut self, arg: &str) -> Result<u32, String> {
+        let mut parts = arg.split('/');
+        let position = parts.next().unwrap_or("");
+        let uv = parts.next().filter(|s| !s.is_empty());
+        let normal = parts.next().filter(|s| !s.is_empty());
+        if parts.next().is_some() {
+            return Err(format!("malformed face vertex '{arg}'"));
+        }
+
+        let key = (resolve_index(position, self.positions.len(), "vertex")?,
+                   uv.map(|uv| resolve_index(uv, self.uvs.len(), "texture coordinate")).transpose()?,
+                   normal.map(|n| resolve_index(n, self.normals.len(), "normal")).transpose()?);
+
+        if let Some(&index) = self.vertex_indices.get(&key) {
+            return Ok(index);
+        }
+        let index = u32::try_from(self.vertices.len()).map_err(|_| "too many vertices".to_string())?;
+        self.vertices.push(key);
+        self.vertex_indices.insert(key, index);
+        Ok(index)
+    }
+
+    fn finish(self, material: Material) -> Result<Mesh, ObjError> {
+        if self.indices.is_empty() {
+            return Err(ObjError::NoFaces);
+        }
+
+        // Attributes are only kept when every vertex has them; a mesh with
+        // partial normals falls back to flat shading throughout.
+        let uvs = self.vertices.iter()
+            .map(|&(_, uv, _)| uv.map(|i| self.uvs[i]))
+            .collect::<Option<Vec<_>>>()
+            .unwrap_or_default();
+        let normals = self.vertices.iter()
+            .map(|&(_, _, n)| n.map(|i| self.normals[i]))
+            .collect::<Option<Vec<_>>>()
+            .unwrap_or_default();
+
+        Ok(Mesh {
+            positions: self.vertices.iter().map(|&(p, _, _)| self.positions[p]).collect(),
+            normals,
+            uvs,
+            indices: self.indices,
+            material,
+        })
+    }
+}
+
+/// Parses between `min` and `max` floats, keeping the first `N` of them and
+/// filling any unspecified ones with zero.
+fn parse_floats<const N: usize>(keyword: &str, args: &[&str], min: usize, max: usize) -> Result<[f32; N], String> {
+    if args.len() < min || args.len() > max {
+        return Err(if min == max {
+            format!("'{keyword}' expects {min} values, found {}", args.len())
+        } else {
+            format!("'{keyword}' expects {min} to {max} values, found {}", args.len())
+        });
+    }
+
+    let mut values = [0.0; N];
+    for (i, arg) in args.iter().enumerate() {
+        let value = arg.parse().map_err(|_| format!("invalid number '{arg}' in '{keyword}'"))?;
+        if i < N {
+            values[i] = value;
+        }
+    }
+    Ok(values)
+}
+
+/// Turns a one-based (or negative, relative) OBJ index into a zero-based one.
+fn resolve_index(s: &str, count: usize, what: &str) -> Result<usize, String> {
+    let index = s.parse::<isize>().map_err(|_| format!("invalid {what} index '{s}'"))?;
+    let resolved = match index {
+        0 => None,
+        i if i > 0 => Some(i.unsigned_abs() - 1),
+        i => count.checked_sub(i.unsigned_abs()),
+    };
+
+    resolved
+        .filter(|&i| i < count)
+        .ok_or_else(|| format!("{what} index {index} is out of range ({count} defined so far)"))
+}
@@ -0,0 +1,87 @@
+use std::f32::consts::PI;
+
+use crate::microfacet;
+use crate::random::{cosine_direction, uniform_sphere_direction};
+use crate::sampler::{PixelSampler, Sampler};
+use crate::types::{Onb, Vec3};
+
+/// Distribution of scattered directions, as solid angle densities.
+#[derive(Clone, Copy)]
+pub enum Pdf {
+    /// Proportional to the cosine with the basis' `w` axis, the ideal choice for Lambertian surfaces.
+    Cosine(Onb),
+    /// Uniform over the whole sphere of directions.
+    Uniform,
+    /// Reflection about GGX microfacet normals visible from `wo`, given in
+    /// `frame`, mixed with a cosine lobe for the diffuse base of a coated surface.
+    Glossy { frame: Onb, wo: Vec3, alpha: f32, specular_probability: f32 },
+    /// `Glossy` with a second specular lobe of width `coat_alpha` for a clear coat.
+    Coated { frame: Onb, wo: Vec3, alpha: f32, coat_alpha: f32, specular_probability: f32, coat_probability: f32 },
+}
+
+impl Pdf {
+    pub fn cosine(normal: &Vec3) -> Pdf {
+        Pdf::Cosine(Onb::from_w(normal))
+    }
+
+    pub fn value(&self, direction: &Vec3) -> f32 {
+        match self {
+            Pdf::Cosine(onb) => (direction.normalize().dot(&onb.w()) / PI).max(0.0),
+            Pdf::Uniform => 1.0 / (4.0 * PI),
+            Pdf::Glossy { frame, wo, alpha, specular_probability } => {
+                let wi = frame.to_local(&direction.normalize());
+                if wi.z <= 0.0 {
+                    return 0.0;
+                }
+                let h = (wo + wi).normalize();
+                let specular = microfacet::visible_reflection_pdf(wo, &h, *alpha);
+                let diffuse = wi.z / PI;
+                specular_probability * specular + (1.0 - specular_probability) * diffuse
+            }
+            Pdf::Coated { frame, wo, alpha, coat_alpha, specular_probability, coat_probability } => {
+                let wi = frame.to_local(&direction.normalize());
+                if wi.z <= 0.0 {
+                    return 0.0;
+                }
+                let h = (wo + wi).normalize();
+                let specular = microfacet::visible_reflection_pdf(wo, &h, *alpha);
+                let coat = microfacet::visible_reflection_pdf(wo, &h, *coat_alpha);
+                let diffuse = wi.z / PI;
+                specular_probability * specular
+                    + coat_probability * coat
+                    + (1.0 - specular_probability - coat_probability) * diffuse
+            }
+        }
+    }
+
+    /// Draws a direction. Every distribution takes one dimension to pick a
+    /// lobe and two to place the direction within it, so that later bounces
+    /// stay on the same sampler dimensions whichever lobe was chosen.
+    pub fn generate(&self, sampler: &mut PixelSampler) -> Vec3 {
+        let choice = sampler.get_1d();
+        let u = sampler.get_2d();
+        match self {
+            Pdf::Cosine(onb) => onb.local(&cosine_direction(u)),
+            Pdf::Uniform => uniform_sphere_direction(u),
+            Pdf::Glossy { frame, wo, alpha, specular_probability } => {
+                if choice < *specular_probability {
+                    let h = microfacet::sample_visible_normal(wo, *alpha, u.0, u.1);
+                    frame.local(&(2.0 * wo.dot(&h) * h - wo))
+                } else {
+                    frame.local(&cosine_direction(u))
+                }
+            }
+            Pdf::Coated { frame, wo, alpha, coat_alpha, specular_probability, coat_probability } => {
+                let lobe_alpha = if choice < *specular_probability {
+                    *alpha
+                } else if choice < specular_probability + coat_probability {
+                    *coat_alpha
+                } else {
+                    return frame.local(&cosine_direction(u));
+                };
+                let h = microfacet::sample_visible_normal(wo, lobe_alpha, u.0, u.1);
+                frame.local(&(2.0 * wo.dot(&h) * h - wo))
+            }
+        }
+    }
+}
@@ -4,8 +4,7 @@
 #![allow(clippy::needless_range_loop)]
 #![allow(clippy::cast_possible_truncation)]
 
-use crate::random::Vector;
-use rand::Rng;
+use crate::random::{Rng, Vector};
 use crate::types::{Point3, Vec3};
 use std::sync::Arc;
 
@@ -42,15 +41,15 @@ fn perlin_interp(c: [[[Vec3; 2]; 2]; 2], u: f32, v: f32, w: f32) -> f32 {
 }
 
 impl Perlin {
-    pub fn new() -> Self {
+    pub fn new(rng: &mut Rng) -> Self {
         let mut ranfloat = [Vec3::new(0.0, 0.0, 0.0); N];
-        for x in ranfloat.iter_mut() {
-            *x = Vec3::rand_range(-1.0, 1.0).normalize();
+        for x in &mut ranfloat {
+            *x = Vec3::rand_range(rng, -1.0, 1.0).normalize();
         }
 
-        let perm_x = Self::generate_perm();
-        let perm_y = Self::generate_perm();
-        let perm_z = Self::generate_perm();
+        let perm_x = Self::generate_perm(rng);
+        let perm_y = Self::generate_perm(rng);
+        let perm_z = Self::generate_perm(rng);
 
         Self {
             ranfloat: Arc::new(ranfloat),
@@ -103,20 +102,19 @@ impl Perlin {
         perlin_interp(c, u, v, w)
     }
 
-    fn generate_perm() -> Arc<[usize; N]> {
+    fn generate_perm(rng: &mut Rng) -> Arc<[usize; N]> {
         let mut result = [0; N];
         for (i, x) in result.iter_mut().enumerate() {
             *x = i;
         }
 
-        Self::permute(&mut result);
+        Self::permute(&mut result, rng);
         Arc::new(result)
     }
 
-    fn permute(arr: &mut [usize; N]) {
-        let mut rng = rand::thread_rng();
+    fn permute(arr: &mut [usize; N], rng: &mut Rng) {
         for i in (1..arr.len()).rev() {
-            let target = rng.gen_range(0, i);
+            let target = rng.rand_index(i);
             arr.swap(i, target);
         }
     }
@@ -1,25 +1,26 @@
-use std::io::Write;
-use crate::types::Color;
-use nalgebra_glm::{clamp_scalar};
+use std::io::{self, Write};
 
-pub fn write_header<W:Write>(writer : &mut W, width : u32, height : u32) {
-    writeln!(writer, "P3").unwrap();
-    writeln!(writer, "{} {}", width, height).unwrap();
-    writeln!(writer, "255").unwrap();
-}
+use crate::image::Image;
 
-pub trait WritePPM {
-    fn write_ppm<W:Write>(&self, writer : &mut W);
+/// Writes a binary (P6) portable pixmap.
+///
+/// # Errors
+///
+/// Propagates any error from `writer`.
+pub fn write_binary<W: Write>(image: &Image, writer: &mut W) -> io::Result<()> {
+    write!(writer, "P6\n{} {}\n255\n", image.width, image.height)?;
+    writer.write_all(&image.to_rgb8())
 }
 
-impl WritePPM for Color {
-    #[allow(clippy::cast_possible_truncation)]
-    #[allow(clippy::cast_sign_loss)]
-    fn write_ppm<W:Write>(&self, writer : &mut W) {
-        let ir = (256.0 * clamp_scalar(self.x, 0.0, 0.999)) as u8;
-        let ig = (256.0 * clamp_scalar(self.y, 0.0, 0.999)) as u8;
-        let ib = (256.0 * clamp_scalar(self.z, 0.0, 0.999)) as u8;
-
-        writeln!(writer, "{} {} {}", ir, ig, ib).unwrap();
+/// Writes an ASCII (P3) portable pixmap, one pixel per line.
+///
+/// # Errors
+///
+/// Propagates any error from `writer`.
+pub fn write_ascii<W: Write>(image: &Image, writer: &mut W) -> io::Result<()> {
+    write!(writer, "P3\n{} {}\n255\n", image.width, image.height)?;
+    for rgb in image.to_rgb8().chunks_exact(3) {
+        writeln!(writer, "{} {} {}", rgb[0], rgb[1], rgb[2])?;
     }
-}
\ No newline at end of file
+    Ok(())
+}
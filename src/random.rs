@@ -1,67 +1,150 @@
-use rand::Rng;
+use rand::{Rng as _, SeedableRng};
+use rand_pcg::Pcg32;
+use crate::sampler::ONE_MINUS_EPSILON;
 use crate::types::Vec3;
 
-pub fn rand() -> f32 {
-    let mut rng = rand::thread_rng();
-    rng.gen()
+/// Seedable source of random numbers. Every pixel sample gets its own stream,
+/// so a render depends only on the seed and not on how work is split between threads.
+#[derive(Clone, Debug)]
+pub struct Rng(Pcg32);
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        Rng(Pcg32::seed_from_u64(seed))
+    }
+
+    /// Generator for sample number `sample` of the pixel with index `pixel`.
+    pub fn for_sample(seed: u64, pixel: u64, sample: u32) -> Rng {
+        Rng(Pcg32::new(mix(seed ^ mix(u64::from(sample))), pixel))
+    }
+
+    /// Uniform number in `[0, 1)`.
+    pub fn rand(&mut self) -> f32 {
+        self.0.gen()
+    }
+
+    pub fn rand_range(&mut self, low: f32, high: f32) -> f32 {
+        self.0.gen_range(low, high)
+    }
+
+    /// Uniform index in `0..len`.
+    pub fn rand_index(&mut self, len: usize) -> usize {
+        self.0.gen_range(0, len)
+    }
 }
 
-pub fn rand_range(low: f32, high: f32) -> f32 {
-    let mut rng = rand::thread_rng();
-    rng.gen_range(low, high)
+/// Finaliser of the `SplitMix64` generator, which spreads nearby inputs over unrelated outputs.
+pub(crate) fn mix(mut x: u64) -> u64 {
+    x = x.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^ (x >> 31)
+}
+
+/// Direction about `+z` with a density proportional to its cosine, placed by
+/// the point `u` of the unit square.
+pub fn cosine_direction(u: (f32, f32)) -> Vec3 {
+    let phi = 2.0 * std::f32::consts::PI * u.0;
+    let x = phi.cos() * u.1.sqrt();
+    let y = phi.sin() * u.1.sqrt();
+    Vec3::new(x, y, (1.0 - u.1).sqrt())
+}
+
+/// Direction uniformly distributed over the sphere, placed by the point `u`
+/// of the unit square.
+pub fn uniform_sphere_direction(u: (f32, f32)) -> Vec3 {
+    let z = 1.0 - 2.0 * u.1;
+    let r = (1.0 - z * z).max(0.0).sqrt();
+    let phi = 2.0 * std::f32::consts::PI * u.0;
+    Vec3::new(r * phi.cos(), r * phi.sin(), z)
+}
+
+/// Direction about `+z` uniformly distributed over the cone subtended by a
+/// sphere of `radius` whose centre lies `distance_squared` away along `+z`,
+/// placed by the point `u` of the unit square.
+pub fn to_sphere_direction(u: (f32, f32), radius: f32, distance_squared: f32) -> Vec3 {
+    let cos_theta_max = (1.0 - radius * radius / distance_squared).max(0.0).sqrt();
+    let z = 1.0 + u.1 * (cos_theta_max - 1.0);
+    let phi = 2.0 * std::f32::consts::PI * u.0;
+    let sin_theta = (1.0 - z * z).max(0.0).sqrt();
+    Vec3::new(phi.cos() * sin_theta, phi.sin() * sin_theta, z)
+}
+
+/// One of `len` choices picked by the uniform number `u`, along with what is
+/// left of `u` rescaled to `[0, 1)`, so that one sample can make several choices.
+pub fn pick(u: f32, len: usize) -> (usize, f32) {
+    let scaled = u * len as f32;
+    let index = (scaled as usize).min(len - 1);
+    (index, (scaled - index as f32).min(ONE_MINUS_EPSILON))
+}
+
+/// Point in the unit disk in the `xy` plane, mapped from the point `u` of the
+/// unit square with Shirley's concentric mapping, which keeps strata intact.
+pub fn concentric_disk(u: (f32, f32)) -> Vec3 {
+    let (x, y) = (2.0 * u.0 - 1.0, 2.0 * u.1 - 1.0);
+    if x == 0.0 && y == 0.0 {
+        return Vec3::new(0.0, 0.0, 0.0);
+    }
+    let (r, theta) = if x.abs() > y.abs() {
+        (x, std::f32::consts::FRAC_PI_4 * (y / x))
+    } else {
+        (y, std::f32::consts::FRAC_PI_2 - std::f32::consts::FRAC_PI_4 * (x / y))
+    };
+    Vec3::new(r * theta.cos(), r * theta.sin(), 0.0)
 }
 
 pub trait Vector {
-    fn rand() -> Vec3;
-    fn rand_range(min: f32, max: f32) -> Vec3;
-    fn rand_unit() -> Vec3;
-    fn rand_in_hemisphere(normal: &Vec3) -> Vec3;
-    fn rand_in_unit_sphere() -> Vec3;
-    fn rand_in_unit_disk() -> Vec3;
+    fn rand(rng: &mut Rng) -> Vec3;
+    fn rand_range(rng: &mut Rng, min: f32, max: f32) -> Vec3;
+    fn rand_unit(rng: &mut Rng) -> Vec3;
+    fn rand_in_hemisphere(rng: &mut Rng, normal: &Vec3) -> Vec3;
+    fn rand_in_unit_sphere(rng: &mut Rng) -> Vec3;
+    fn rand_in_unit_disk(rng: &mut Rng) -> Vec3;
+    fn rand_cosine_direction(rng: &mut Rng) -> Vec3;
 }
 
 impl Vector for Vec3 {
-    fn rand() -> Vec3 {
-        Self::new(rand(), rand(), rand())
+    fn rand(rng: &mut Rng) -> Vec3 {
+        Self::new(rng.rand(), rng.rand(), rng.rand())
     }
 
-    fn rand_range(min: f32, max: f32) -> Vec3 {
-        Self::new(rand_range(min, max), rand_range(min, max), rand_range(min, max))
+    fn rand_range(rng: &mut Rng, min: f32, max: f32) -> Vec3 {
+        Self::new(rng.rand_range(min, max), rng.rand_range(min, max), rng.rand_range(min, max))
     }
 
-    fn rand_unit() -> Vec3 {
-        let mut rng = rand::thread_rng();
-        let mut p = 2.0 * Vec3::new(rng.gen::<f32>(), rng.gen(), rng.gen()) - Vec3::new(1.0, 1.0, 1.0);
-        while p.magnitude_squared() >= 1.0 {
-            p = 2.0 * Vec3::new(rng.gen(), rng.gen(), rng.gen()) - Vec3::new(1.0, 1.0, 1.0);
-        }
-        p.normalize()
+    fn rand_unit(rng: &mut Rng) -> Vec3 {
+        Self::rand_in_unit_sphere(rng).normalize()
     }
 
-    fn rand_in_hemisphere(normal: &Vec3) -> Vec3 {
-        let p = Self::rand_in_unit_sphere();
-        if p.dot(&normal) > 0.0 {
+    fn rand_in_hemisphere(rng: &mut Rng, normal: &Vec3) -> Vec3 {
+        let p = Self::rand_in_unit_sphere(rng);
+        if p.dot(normal) > 0.0 {
             p
         } else {
             -p
         }
     }
 
-    fn rand_in_unit_sphere() -> Vec3 {
-        let mut rng = rand::thread_rng();
-        let mut p = 2.0 * Vec3::new(rng.gen(), rng.gen(), rng.gen()) - Vec3::new(1.0, 1.0, 1.0);
-        while p.magnitude_squared() >= 1.0 {
-            p = 2.0 * Vec3::new(rng.gen(), rng.gen(), rng.gen()) - Vec3::new(1.0, 1.0, 1.0);
+    fn rand_in_unit_sphere(rng: &mut Rng) -> Vec3 {
+        loop {
+            let p = 2.0 * Self::rand(rng) - Vec3::new(1.0, 1.0, 1.0);
+            if p.magnitude_squared() < 1.0 {
+                return p;
+            }
         }
-        p
     }
 
-    fn rand_in_unit_disk() -> Vec3 {
-        let mut rng = rand::thread_rng();
-        let mut p = Vec3::new(rng.gen_range(-1.0, 1.0), rng.gen_range(-1.0, 1.0), 0.0);
-        while p.magnitude_squared() >= 1.0 {
-            p = Vec3::new(rng.gen_range(-1.0, 1.0), rng.gen_range(-1.0, 1.0), 0.0)
+    fn rand_in_unit_disk(rng: &mut Rng) -> Vec3 {
+        loop {
+            let p = Vec3::new(rng.rand_range(-1.0, 1.0), rng.rand_range(-1.0, 1.0), 0.0);
+            if p.magnitude_squared() < 1.0 {
+                return p;
+            }
         }
-        p
     }
-}
\ No newline at end of file
+
+    /// Direction about `+z` with a density proportional to its cosine.
+    fn rand_cosine_direction(rng: &mut Rng) -> Vec3 {
+        cosine_direction((rng.rand(), rng.rand()))
+    }
+}
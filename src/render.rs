@@ -0,0 +1,401 @@
+use indicatif::{ProgressBar, ProgressDrawTarget, ProgressStyle};
+use rayon::prelude::*;
+
+use crate::camera::Camera;
+use crate::film::Film;
+use crate::filter::Filter;
+use crate::image::Image;
+use crate::intersections::{HitRecord, Hittable};
+use crate::material::{Scatter, ScatterRecord};
+use crate::pdf::Pdf;
+use crate::random::Rng;
+use crate::sampler::{PixelSampler, Sampler, SamplerKind};
+use crate::scenes::Scene;
+use crate::tile::{self, Tile, TileOrder};
+use crate::tonemap::{luminance, ToneMapping};
+use crate::types::{Color, Ray};
+
+/// How multiple importance sampling weighs a light sample against a BSDF
+/// sample that could have produced the same direction.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum MisHeuristic {
+    Balance,
+    /// Veach's power heuristic with an exponent of 2.
+    Power,
+}
+
+impl MisHeuristic {
+    /// Weight of a sample drawn with density `pdf`, where `other_pdf` is the
+    /// density of the competing strategy for the same direction.
+    pub fn weight(self, pdf: f32, other_pdf: f32) -> f32 {
+        match self {
+            MisHeuristic::Balance => pdf / (pdf + other_pdf),
+            MisHeuristic::Power => pdf * pdf / (pdf * pdf + other_pdf * other_pdf),
+        }
+    }
+}
+
+/// Stops sampling a pixel once its estimate is good enough, rather than
+/// always taking `samples_per_pixel` samples.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct AdaptiveSampling {
+    /// Samples every pixel takes before its error is first estimated. The
+    /// error is estimated again each time the count doubles.
+    pub min_samples: u32,
+    /// Standard error of a pixel's luminance, relative to the luminance
+    /// itself, below which the pixel is done.
+    pub threshold: f32,
+}
+
+impl Default for AdaptiveSampling {
+    fn default() -> Self {
+        AdaptiveSampling { min_samples: 16, threshold: 0.05 }
+    }
+}
+
+impl AdaptiveSampling {
+    /// Whether `n` samples with the running luminance statistics `mean` and
+    /// `sum_squares` (the sum of squared deviations from the mean) are enough.
+    fn converged(self, n: u32, mean: f32, sum_squares: f32) -> bool {
+        let n = n as f32;
+        let standard_error = (sum_squares / ((n - 1.0) * n)).sqrt();
+        standard_error <= self.threshold * mean.max(DARK_LUMINANCE)
+    }
+}
+
+/// Pixels darker than this are held to the same absolute error as one this
+/// bright, so that dim noise does not keep them sampling forever.
+const DARK_LUMINANCE: f32 = 0.01;
+
+#[derive(Clone, Debug)]
+pub struct RenderSettings {
+    pub image_width: u32,
+    pub image_height: u32,
+    /// Samples taken by every pixel, or the most any pixel takes with adaptive sampling.
+    pub samples_per_pixel: u32,
+    /// Lets pixels stop early once their noise is low enough, or `None` to
+    /// always take `samples_per_pixel` samples.
+    pub adaptive: Option<AdaptiveSampling>,
+    /// Hard limit on the number of bounces, or `None` to leave path
+    /// termination to Russian roulette alone.
+    pub max_depth: Option<u32>,
+    /// Number of bounces before Russian roulette starts terminating paths.
+    pub rr_min_depth: u32,
+    pub mis_heuristic: MisHeuristic,
+    pub tone_mapping: ToneMapping,
+    pub show_progress: bool,
+    /// Seed of the random numbers used for sampling. The same seed always
+    /// produces the same image, however many threads render it.
+    pub seed: u64,
+    /// How the pixel, lens, time and scattering dimensions of each sample are placed.
+    pub sampler: SamplerKind,
+    /// Filter spreading each sample over the pixels around it.
+    pub filter: Filter,
+    /// Width and height of the square tiles the image is rendered in.
+    pub tile_size: u32,
+    pub tile_order: TileOrder,
+}
+
+impl Default for RenderSettings {
+    fn default() -> Self {
+        RenderSettings {
+            image_width: 500,
+            image_height: 281,
+            samples_per_pixel: 50,
+            adaptive: None,
+            max_depth: None,
+            rr_min_depth: 3,
+            mis_heuristic: MisHeuristic::Power,
+            tone_mapping: ToneMapping::default(),
+            show_progress: false,
+            seed: 0,
+            sampler: SamplerKind::Sobol,
+            filter: Filter::default(),
+            tile_size: 32,
+            tile_order: TileOrder::Hilbert,
+        }
+    }
+}
+
+pub struct Renderer {
+    pub settings: RenderSettings,
+}
+
+/// Renders `scene` with the given settings on the global rayon thread pool.
+pub fn render(scene: &Scene, settings: &RenderSettings) -> Image {
+    Renderer::new(settings.clone()).render(scene)
+}
+
+impl Renderer {
+    pub fn new(settings: RenderSettings) -> Renderer {
+        Renderer { settings }
+    }
+
+    /// Follows a path from `ray` through the scene, accumulating the light
+    /// carried back along it. Scattering, light and medium samples come from
+    /// `sampler`, and `rng` only plays Russian roulette.
+    pub fn ray_color(&self, ray: &Ray, scene: &Scene, sampler: &mut PixelSampler, rng: &mut Rng) -> Color {
+        let world = &scene.world;
+        let mut radiance = Color::new(0.0, 0.0, 0.0);
+        let mut throughput = Color::new(1.0, 1.0, 1.0);
+        let mut ray = *ray;
+        // Density with which the previous bounce sampled `ray`; `None` for
+        // camera rays and specular bounces, where lights were not sampled and
+        // any emission hit counts in full.
+        let mut bsdf_pdf = None;
+        let mut bounces = 0;
+
+        while self.settings.max_depth.is_none_or(|max_depth| bounces < max_depth) {
+            ray = ray.with_medium_sample(sampler.get_1d());
+            let hit = world.hit(&ray, 0.001, f32::INFINITY);
+            let mut emitted = match &hit {
+                Some(hit) => hit.material.emitted(hit.u, hit.v, hit.point),
+                None => scene.environment.color(&ray),
+            };
+            if let Some(bsdf_pdf) = bsdf_pdf {
+                let light_pdf = scene.light_pdf(&ray.origin(), &ray.direction());
+                if light_pdf > 0.0 {
+                    emitted *= self.settings.mis_heuristic.weight(bsdf_pdf, light_pdf);
+                }
+            }
+            radiance += throughput.component_mul(&emitted);
+
+            let Some(hit) = hit else {
+                break;
+            };
+
+            match hit.material.scatter(&ray, &hit, sampler) {
+                None => break,
+                Some(ScatterRecord::Specular { ray: scattered, attenuation }) => {
+                    throughput.component_mul_assign(&attenuation);
+                    ray = scattered;
+                    bsdf_pdf = None;
+                }
+                Some(ScatterRecord::Sampled { pdf }) => {
+                    radiance += throughput.component_mul(&self.direct_light(&ray, &hit, scene, &pdf, sampler));
+
+                    let scattered = Ray::new(hit.point, pdf.generate(sampler), ray.time);
+                    let pdf_value = pdf.value(&scattered.direction());
+                    if pdf_value <= 0.0 {
+                        break;
+                    }
+                    let f = hit.material.eval(&ray, &hit, &scattered.direction());
+                    throughput.component_mul_assign(&(f / pdf_value));
+                    ray = scattered;
+                    bsdf_pdf = Some(pdf_value);
+                }
+            }
+
+            bounces += 1;
+            if bounces >= self.settings.rr_min_depth {
+                // Surviving paths are boosted by the inverse of their survival
+                // probability, which keeps the estimate unbiased. The cap keeps
+                // paths with a throughput near 1 from bouncing forever.
+                let survival = throughput.max().min(0.95);
+                if rng.rand() >= survival {
+                    break;
+                }
+                throughput /= survival;
+            }
+        }
+
+        radiance
+    }
+
+    /// Light reaching `hit` from one sampled point on a light, weighted against
+    /// the chance of the BSDF sample in `trace` finding the same light.
+    fn direct_light(&self, ray: &Ray, hit: &HitRecord, scene: &Scene, pdf: &Pdf, sampler: &mut PixelSampler) -> Color {
+        let black = Color::new(0.0, 0.0, 0.0);
+        let Some(direction) = scene.sample_light(&hit.point, sampler) else {
+            return black;
+        };
+        let light_pdf = scene.light_pdf(&hit.point, &direction);
+        if light_pdf <= 0.0 {
+            return black;
+        }
+
+        let f = hit.material.eval(ray, hit, &direction);
+        if f.max() <= 0.0 {
+            return black;
+        }
+        let shadow_ray = Ray::new(hit.point, direction, ray.time).with_medium_sample(sampler.get_1d());
+        let emitted = match scene.world.hit(&shadow_ray, 0.001, f32::INFINITY) {
+            Some(light) => light.material.emitted(light.u, light.v, light.point),
+            None => scene.environment.color(&shadow_ray),
+        };
+
+        let weight = self.settings.mis_heuristic.weight(light_pdf, pdf.value(&direction));
+        f.component_mul(&emitted) * weight / light_pdf
+    }
+
+    pub fn render(&self, scene: &Scene) -> Image {
+        self.render_passes(scene, &[self.settings.samples_per_pixel], |_| {})
+    }
+
+    /// Renders in passes that double the samples per pixel, 1, 2, 4 and so
+    /// on up to `samples_per_pixel`, handing the image so far to `on_pass`
+    /// after every pass but the last, whose image is returned.
+    pub fn render_progressive<F: FnMut(&Image)>(&self, scene: &Scene, on_pass: F) -> Image {
+        let samples_per_pixel = self.settings.samples_per_pixel;
+        let mut passes: Vec<u32> = std::iter::successors(Some(1u32), |&n| n.checked_mul(2))
+            .take_while(|&n| n < samples_per_pixel)
+            .collect();
+        passes.push(samples_per_pixel);
+        self.render_passes(scene, &passes, on_pass)
+    }
+
+    /// Renders every tile up to each of the sample counts in `passes` in turn.
+    fn render_passes<F: FnMut(&Image)>(&self, scene: &Scene, passes: &[u32], mut on_pass: F) -> Image {
+        let image_width = self.settings.image_width;
+        let image_height = self.settings.image_height;
+
+        let cam = scene.camera(image_width as f32 / image_height as f32);
+        let tiles = tile::tiles(image_width, image_height, self.settings.tile_size, self.settings.tile_order);
+
+        let pb = if self.settings.show_progress {
+            ProgressBar::new((tiles.len() * passes.len()) as u64)
+        } else {
+            ProgressBar::hidden()
+        };
+        if self.settings.show_progress {
+            pb.set_draw_target(ProgressDrawTarget::stdout());
+        }
+        pb.set_draw_delta(pb.length() / 100);
+        pb.set_style(ProgressStyle::default_bar().template(
+            "[{elapsed} elapsed] {wide_bar:.cyan/white} {percent}% [{eta} remaining] [rendering]",
+        ));
+
+        let image_area = Tile { left: 0, top: 0, width: image_width, height: image_height };
+        let mut film = Film::new(image_area);
+        let mut progress: Vec<Vec<PixelProgress>> = tiles
+            .iter()
+            .map(|tile| vec![PixelProgress::new(self.settings.adaptive); (tile.width * tile.height) as usize])
+            .collect();
+
+        let mut image = Image::new(image_width, image_height);
+        image.tone_mapping = self.settings.tone_mapping;
+        for (pass, &samples) in passes.iter().enumerate() {
+            // Idle threads take the next tile in order, but the tiles' films
+            // are merged by position in the list, so the result does not
+            // depend on how many threads there are or which got what
+            let mut rendered = tiles
+                .iter()
+                .zip(progress.drain(..))
+                .enumerate()
+                .par_bridge()
+                .map(|(index, (tile, mut pixels))| {
+                    let tile_film = self.render_tile(scene, &cam, tile, &mut pixels, samples);
+                    pb.inc(1);
+                    (index, tile_film, pixels)
+                })
+                .collect::<Vec<_>>();
+            rendered.sort_unstable_by_key(|(index, _, _)| *index);
+            for (_, tile_film, pixels) in rendered {
+                film.merge(&tile_film);
+                progress.push(pixels);
+            }
+
+            image.pixels = film.pixels();
+            if pass + 1 < passes.len() {
+                on_pass(&image);
+            }
+        }
+
+        pb.finish();
+
+        if self.settings.adaptive.is_some() {
+            let mut sample_counts = vec![0; (image_width * image_height) as usize];
+            for (tile, pixels) in tiles.iter().zip(&progress) {
+                for (i, pixel) in pixels.iter().enumerate() {
+                    let (x, y) = (tile.left + i as u32 % tile.width, tile.top + i as u32 / tile.width);
+                    sample_counts[(y * image_width + x) as usize] = pixel.samples;
+                }
+            }
+            image.sample_counts = Some(sample_counts);
+        }
+        image
+    }
+
+    /// Samples the pixels of `tile` until each has taken `target_samples`
+    /// samples or converged, into a film reaching as far as the filter spreads them.
+    fn render_tile(&self, scene: &Scene, cam: &Camera, tile: &Tile, pixels: &mut [PixelProgress],
+                   target_samples: u32) -> Film {
+        let image_width = self.settings.image_width;
+        let image_height = self.settings.image_height;
+        let filter = self.settings.filter;
+
+        let margin = (filter.radius + 0.5).ceil() as u32;
+        let (left, top) = (tile.left.saturating_sub(margin), tile.top.saturating_sub(margin));
+        let mut film = Film::new(Tile {
+            left,
+            top,
+            width: (tile.left + tile.width + margin).min(image_width) - left,
+            height: (tile.top + tile.height + margin).min(image_height) - top,
+        });
+        let mut sampler = PixelSampler::new(self.settings.sampler, self.settings.seed,
+                                            self.settings.samples_per_pixel);
+
+        for (index, progress) in pixels.iter_mut().enumerate() {
+            let (i, row) = (tile.left + index as u32 % tile.width, tile.top + index as u32 / tile.width);
+            // The camera counts rows from the bottom
+            let camera_row = image_height - 1 - row;
+            let pixel = u64::from(camera_row) * u64::from(image_width) + u64::from(i);
+            while progress.samples < target_samples && !progress.converged {
+                sampler.start_sample(pixel, progress.samples);
+                let rng = &mut Rng::for_sample(self.settings.seed, pixel, progress.samples);
+                let (dx, dy) = sampler.get_2d();
+                let u = (i as f32 + dx) / (image_width - 1) as f32;
+                let v = (camera_row as f32 + dy) / (image_height - 1) as f32;
+
+                let ray = cam.get_ray(u, v, &mut sampler);
+                let color = self.ray_color(&ray, scene, &mut sampler, rng);
+                film.add_sample(i as f32 + dx, (row + 1) as f32 - dy, &color, filter);
+                progress.add(luminance(color), self.settings.adaptive);
+            }
+        }
+
+        film
+    }
+}
+
+/// How far one pixel has got through its samples.
+#[derive(Clone, Copy, Debug)]
+struct PixelProgress {
+    samples: u32,
+    /// Running mean of the samples' luminance and sum of their squared
+    /// deviations from it (Welford's method).
+    mean: f32,
+    sum_squares: f32,
+    /// Sample count at which adaptive sampling next estimates the error.
+    /// Checking only at doubling counts gives a lucky run of dim samples
+    /// fewer chances to end a pixel early, which would bias it dark.
+    next_check: u32,
+    converged: bool,
+}
+
+impl PixelProgress {
+    fn new(adaptive: Option<AdaptiveSampling>) -> PixelProgress {
+        PixelProgress {
+            samples: 0,
+            mean: 0.0,
+            sum_squares: 0.0,
+            next_check: adaptive.map_or(0, |adaptive| adaptive.min_samples.max(2)),
+            converged: false,
+        }
+    }
+
+    /// Counts a sample of luminance `y`.
+    fn add(&mut self, y: f32, adaptive: Option<AdaptiveSampling>) {
+        self.samples += 1;
+        let Some(adaptive) = adaptive else {
+            return;
+        };
+        let delta = y - self.mean;
+        self.mean += delta / self.samples as f32;
+        self.sum_squares += delta * (y - self.mean);
+        if self.samples == self.next_check {
+            self.converged = adaptive.converged(self.samples, self.mean, self.sum_squares);
+            self.next_check *= 2;
+        }
+    }
+}
@@ -0,0 +1,374 @@
+//! Sources of the sample values that place a path: its position within the
+//! pixel, on the lens, in time, and its scattering directions.
+//!
+//! Each value is requested as a one or two dimensional sample. Consecutive
+//! samples of one pixel fill these dimensions more evenly than independent
+//! random numbers, which makes images converge faster.
+
+use enum_dispatch::enum_dispatch;
+
+use crate::random::{mix, Rng};
+
+#[enum_dispatch(PixelSampler)]
+pub trait Sampler {
+    /// Moves to sample `index` of the pixel with index `pixel`, starting over
+    /// at the first dimension.
+    fn start_sample(&mut self, pixel: u64, index: u32);
+
+    /// Next dimension, in `[0, 1)`.
+    fn get_1d(&mut self) -> f32;
+
+    /// Next two dimensions, in `[0, 1)²`.
+    fn get_2d(&mut self) -> (f32, f32);
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SamplerKind {
+    Independent,
+    Stratified,
+    Halton,
+    Sobol,
+}
+
+#[enum_dispatch]
+#[derive(Clone, Debug)]
+pub enum PixelSampler {
+    Independent,
+    Stratified,
+    Halton,
+    Sobol,
+}
+
+impl PixelSampler {
+    /// A sampler of the given kind for pixels taking `samples_per_pixel` samples each.
+    pub fn new(kind: SamplerKind, seed: u64, samples_per_pixel: u32) -> PixelSampler {
+        // Kept apart from the streams `Rng::for_sample` hands to the renderer
+        let seed = mix(seed ^ 0x5a3d_1e2f_7c4b_9a60);
+        match kind {
+            SamplerKind::Independent => PixelSampler::from(Independent { seed, rng: Rng::new(seed) }),
+            SamplerKind::Stratified => PixelSampler::from(Stratified {
+                seed,
+                samples_per_pixel,
+                state: State::default(),
+                rng: Rng::new(seed),
+            }),
+            SamplerKind::Halton => PixelSampler::from(Halton { seed, state: State::default() }),
+            SamplerKind::Sobol => PixelSampler::from(Sobol { seed, state: State::default() }),
+        }
+    }
+}
+
+/// Position within the sample sequence shared by the structured samplers.
+#[derive(Clone, Copy, Debug, Default)]
+struct State {
+    pixel: u64,
+    index: u32,
+    dimension: u32,
+}
+
+impl State {
+    fn start(&mut self, pixel: u64, index: u32) {
+        *self = State { pixel, index, dimension: 0 };
+    }
+
+    /// Hash identifying the next dimension of the current pixel, advancing past it.
+    fn next_dimension(&mut self, seed: u64, width: u32) -> u64 {
+        let hash = mix(seed ^ mix(self.pixel ^ mix(u64::from(self.dimension))));
+        self.dimension += width;
+        hash
+    }
+}
+
+/// Uniform random numbers, as if there were no sampler at all.
+#[derive(Clone, Debug)]
+pub struct Independent {
+    seed: u64,
+    rng: Rng,
+}
+
+impl Sampler for Independent {
+    fn start_sample(&mut self, pixel: u64, index: u32) {
+        self.rng = Rng::for_sample(self.seed, pixel, index);
+    }
+
+    fn get_1d(&mut self) -> f32 {
+        self.rng.rand()
+    }
+
+    fn get_2d(&mut self) -> (f32, f32) {
+        (self.rng.rand(), self.rng.rand())
+    }
+}
+
+/// Jittered sampling: every dimension is divided into one stratum per sample
+/// of the pixel, or a grid of them in two dimensions, and each sample takes
+/// a random point in a different stratum.
+#[derive(Clone, Debug)]
+pub struct Stratified {
+    seed: u64,
+    samples_per_pixel: u32,
+    state: State,
+    rng: Rng,
+}
+
+impl Sampler for Stratified {
+    fn start_sample(&mut self, pixel: u64, index: u32) {
+        self.state.start(pixel, index);
+        self.rng = Rng::for_sample(self.seed, pixel, index);
+    }
+
+    fn get_1d(&mut self) -> f32 {
+        let hash = self.state.next_dimension(self.seed, 1);
+        let strata = self.samples_per_pixel;
+        let stratum = permute(self.state.index % strata, strata, hash as u32);
+        (stratum as f32 + self.rng.rand()) / strata as f32
+    }
+
+    fn get_2d(&mut self) -> (f32, f32) {
+        let hash = self.state.next_dimension(self.seed, 2);
+        // The smallest grid with at least one cell per sample
+        let columns = (self.samples_per_pixel as f32).sqrt().ceil() as u32;
+        let rows = self.samples_per_pixel.div_ceil(columns);
+        let stratum = permute(self.state.index % (columns * rows), columns * rows, hash as u32);
+        let x = (stratum % columns) as f32 + self.rng.rand();
+        let y = (stratum / columns) as f32 + self.rng.rand();
+        (x / columns as f32, y / rows as f32)
+    }
+}
+
+/// The Halton sequence, giving every dimension its own prime base, shifted by
+/// a random offset per pixel and dimension. Dimensions past the last prime
+/// fall back to hashed random numbers.
+#[derive(Clone, Debug)]
+pub struct Halton {
+    seed: u64,
+    state: State,
+}
+
+const PRIMES: [u32; 32] = [
+    2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53,
+    59, 61, 67, 71, 73, 79, 83, 89, 97, 101, 103, 107, 109, 113, 127, 131,
+];
+
+impl Halton {
+    fn sample(&self, dimension: u32, hash: u64) -> f32 {
+        let offset = unit(hash as u32);
+        let Some(&base) = PRIMES.get(dimension as usize) else {
+            return unit((hash >> 32) as u32 ^ self.state.index.wrapping_mul(0x9e37_79b9));
+        };
+        let value = radical_inverse(base, self.state.index) + offset;
+        // Wrapping around keeps the value uniform; the clamp guards against
+        // rounding up to exactly 1
+        (if value >= 1.0 { value - 1.0 } else { value }).min(ONE_MINUS_EPSILON)
+    }
+}
+
+impl Sampler for Halton {
+    fn start_sample(&mut self, pixel: u64, index: u32) {
+        self.state.start(pixel, index);
+    }
+
+    fn get_1d(&mut self) -> f32 {
+        let dimension = self.state.dimension;
+        let hash = self.state.next_dimension(self.seed, 1);
+        self.sample(dimension, hash)
+    }
+
+    fn get_2d(&mut self) -> (f32, f32) {
+        let dimension = self.state.dimension;
+        let hash = self.state.next_dimension(self.seed, 2);
+        (self.sample(dimension, hash), self.sample(dimension + 1, mix(hash)))
+    }
+}
+
+/// The first two dimensions of the Sobol sequence with hash-based Owen
+/// scrambling, reused for every pair of dimensions by shuffling the order of
+/// the samples differently each time (Burley 2020, "Practical Hash-based Owen
+/// Scrambling").
+#[derive(Clone, Debug)]
+pub struct Sobol {
+    seed: u64,
+    state: State,
+}
+
+impl Sobol {
+    fn sample(&mut self, width: u32) -> (f32, f32) {
+        let hash = self.state.next_dimension(self.seed, width);
+        let index = nested_uniform_scramble(self.state.index, hash as u32);
+        let x = nested_uniform_scramble(index.reverse_bits(), (hash >> 32) as u32);
+        let y = nested_uniform_scramble(sobol_second_dimension(index), mix(hash) as u32);
+        (unit(x), unit(y))
+    }
+}
+
+impl Sampler for Sobol {
+    fn start_sample(&mut self, pixel: u64, index: u32) {
+        self.state.start(pixel, index);
+    }
+
+    fn get_1d(&mut self) -> f32 {
+        self.sample(1).0
+    }
+
+    fn get_2d(&mut self) -> (f32, f32) {
+        self.sample(2)
+    }
+}
+
+/// Largest `f32` below 1.
+pub(crate) const ONE_MINUS_EPSILON: f32 = 1.0 - f32::EPSILON / 2.0;
+
+/// Maps 32 random bits to `[0, 1)`.
+pub(crate) fn unit(bits: u32) -> f32 {
+    (bits >> 8) as f32 / (1u32 << 24) as f32
+}
+
+/// Digits of `index` in `base`, mirrored about the radix point.
+fn radical_inverse(base: u32, mut index: u32) -> f32 {
+    let inv_base = 1.0 / f64::from(base);
+    let mut reversed = 0.0;
+    let mut scale = inv_base;
+    while index > 0 {
+        reversed += f64::from(index % base) * scale;
+        index /= base;
+        scale *= inv_base;
+    }
+    reversed as f32
+}
+
+/// Generator matrix of the second Sobol dimension, from the primitive polynomial `x + 1`.
+const SOBOL_DIRECTIONS: [u32; 32] = {
+    let mut directions = [0; 32];
+    directions[0] = 1 << 31;
+    let mut i = 1;
+    while i < 32 {
+        directions[i] = directions[i - 1] ^ (directions[i - 1] >> 1);
+        i += 1;
+    }
+    directions
+};
+
+fn sobol_second_dimension(index: u32) -> u32 {
+    let mut result = 0;
+    for (bit, direction) in SOBOL_DIRECTIONS.iter().enumerate() {
+        if index >> bit & 1 == 1 {
+            result ^= direction;
+        }
+    }
+    result
+}
+
+/// Laine and Karras' hash, which only lets each bit affect higher bits.
+fn laine_karras_permutation(mut x: u32, seed: u32) -> u32 {
+    x = x.wrapping_add(seed);
+    x ^= x.wrapping_mul(0x6c50_b47c);
+    x ^= x.wrapping_mul(0xb82f_1e52);
+    x ^= x.wrapping_mul(0xc7af_e638);
+    x ^= x.wrapping_mul(0x8d22_f6e6);
+    x
+}
+
+/// Owen scrambling of a fixed-point number in `[0, 1)`: each bit is flipped
+/// depending on the bits above it.
+fn nested_uniform_scramble(x: u32, seed: u32) -> u32 {
+    laine_karras_permutation(x.reverse_bits(), seed).reverse_bits()
+}
+
+/// Element `i` of a random permutation of `0..len` chosen by `seed`
+/// (Kensler 2013, "Correlated Multi-Jittered Sampling").
+fn permute(mut i: u32, len: u32, seed: u32) -> u32 {
+    let mut mask = len.wrapping_sub(1);
+    mask |= mask >> 1;
+    mask |= mask >> 2;
+    mask |= mask >> 4;
+    mask |= mask >> 8;
+    mask |= mask >> 16;
+
+    // Permute within the next power of two, repeating until the result lands in range
+    loop {
+        i ^= seed;
+        i = i.wrapping_mul(0xe170_893d);
+        i ^= seed >> 16;
+        i ^= (i & mask) >> 4;
+        i ^= seed >> 8;
+        i = i.wrapping_mul(0x0929_eb3f);
+        i ^= seed >> 23;
+        i ^= (i & mask) >> 1;
+        i = i.wrapping_mul(1 | seed >> 27);
+        i = i.wrapping_mul(0x6935_fa69);
+        i ^= (i & mask) >> 11;
+        i = i.wrapping_mul(0x74dc_b303);
+        i ^= (i & mask) >> 2;
+        i = i.wrapping_mul(0x9e50_1cc3);
+        i ^= (i & mask) >> 2;
+        i = i.wrapping_mul(0xc860_a3df);
+        i &= mask;
+        i ^= i >> 5;
+        if i < len {
+            break;
+        }
+    }
+    i.wrapping_add(seed) % len
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn permute_is_a_bijection() {
+        for len in [1, 2, 3, 7, 16, 100, 1000] {
+            for seed in [0, 1, 0x1234_5678, 0xdead_beef] {
+                let mut images: Vec<u32> = (0..len).map(|i| permute(i, len, seed)).collect();
+                images.sort_unstable();
+                assert_eq!(images, (0..len).collect::<Vec<_>>(), "len {len}, seed {seed:#x}");
+            }
+        }
+    }
+
+    #[test]
+    fn radical_inverse_mirrors_digits() {
+        let cases = [
+            (2, 0, 0.0), (2, 1, 0.5), (2, 2, 0.25), (2, 3, 0.75), (2, 6, 0.375),
+            (3, 1, 1.0 / 3.0), (3, 2, 2.0 / 3.0), (3, 3, 1.0 / 9.0), (3, 5, 7.0 / 9.0),
+            (5, 7, 2.0 / 5.0 + 1.0 / 25.0),
+        ];
+        for (base, index, expected) in cases {
+            let value = radical_inverse(base, index);
+            assert!((value - expected).abs() < 1e-6, "base {} index {}: {} != {}", base, index, value, expected);
+        }
+    }
+
+    #[test]
+    fn sobol_second_dimension_matches_the_sequence() {
+        // 0, 1/2, 3/4, 1/4, 5/8, 1/8, 3/8, 7/8 as 32-bit fractions
+        let expected = [
+            0, 0x8000_0000, 0xc000_0000, 0x4000_0000, 0xa000_0000, 0x2000_0000, 0x6000_0000, 0xe000_0000,
+        ];
+        for (index, expected) in (0..).zip(expected) {
+            assert_eq!(sobol_second_dimension(index), expected, "index {index}");
+        }
+    }
+
+    #[test]
+    fn stratified_takes_one_sample_per_stratum() {
+        for samples_per_pixel in [1, 8, 16] {
+            let mut sampler = PixelSampler::new(SamplerKind::Stratified, 7, samples_per_pixel);
+            let columns = (samples_per_pixel as f32).sqrt().ceil() as u32;
+            let rows = samples_per_pixel.div_ceil(columns);
+            let mut strata_1d = Vec::new();
+            let mut cells_2d = Vec::new();
+            for index in 0..samples_per_pixel {
+                sampler.start_sample(42, index);
+                strata_1d.push((sampler.get_1d() * samples_per_pixel as f32) as u32);
+                let (x, y) = sampler.get_2d();
+                cells_2d.push((y * rows as f32) as u32 * columns + (x * columns as f32) as u32);
+            }
+            strata_1d.sort_unstable();
+            assert_eq!(strata_1d, (0..samples_per_pixel).collect::<Vec<_>>());
+            cells_2d.sort_unstable();
+            cells_2d.dedup();
+            assert_eq!(cells_2d.len(), samples_per_pixel as usize);
+        }
+    }
+}
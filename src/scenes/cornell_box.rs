@@ -0,0 +1,48 @@
+use crate::environment::Environment;
+use crate::intersections::{Cuboid, World, XYRect, XZRect, YZRect};
+use crate::material::{DiffuseLight, Lambertian, Material};
+use crate::scenes::Scene;
+use crate::transform::Transform;
+use crate::types::{Color, Point3, Vec3};
+
+pub fn scene() -> Scene {
+    Scene {
+        world: make_world(),
+        environment: Environment::Constant(Color::new(0.0, 0.0, 0.0)),
+        vfov: 40.0,
+        aperture: 0.0,
+        lookfrom: Point3::new(278.0, 278.0, -800.0),
+        lookat: Point3::new(278.0, 278.0, 0.0),
+    }
+}
+
+fn make_world() -> World {
+    let mut world = World::new();
+
+    let red = Material::from(Lambertian::from_color(Color::new(0.65, 0.05, 0.05)));
+    let white = Material::from(Lambertian::from_color(Color::new(0.73, 0.73, 0.73)));
+    let green = Material::from(Lambertian::from_color(Color::new(0.12, 0.45, 0.15)));
+    let light = Material::from(DiffuseLight::from_color(Color::new(15.0, 15.0, 15.0)));
+
+    world.add(YZRect { y0: 0.0, y1: 555.0, z0: 0.0, z1: 555.0, k: 555.0, material: green });
+    world.add(YZRect { y0: 0.0, y1: 555.0, z0: 0.0, z1: 555.0, k: 0.0, material: red });
+    world.add(XZRect { x0: 213.0, x1: 343.0, z0: 227.0, z1: 332.0, k: 554.0, material: light });
+    world.add(XZRect { x0: 0.0, x1: 555.0, z0: 0.0, z1: 555.0, k: 0.0, material: white.clone() });
+    world.add(XZRect { x0: 0.0, x1: 555.0, z0: 0.0, z1: 555.0, k: 555.0, material: white.clone() });
+    world.add(XYRect { x0: 0.0, x1: 555.0, y0: 0.0, y1: 555.0, k: 555.0, material: white.clone() });
+
+    let (tall, short) = boxes(&white);
+    world.add(Transform::new(tall).rotate_y(15.0).translate(Vec3::new(265.0, 0.0, 295.0)));
+    world.add(Transform::new(short).rotate_y(-18.0).translate(Vec3::new(130.0, 0.0, 65.0)));
+
+    world.build_bvh();
+    world
+}
+
+/// The tall and the short box standing in the room, before being turned and moved into place.
+pub(crate) fn boxes(material: &Material) -> (Cuboid, Cuboid) {
+    let corner = Point3::new(0.0, 0.0, 0.0);
+    let tall = Cuboid::new(corner, Point3::new(165.0, 330.0, 165.0), material.clone()).expect("the tall box is not flat");
+    let short = Cuboid::new(corner, Point3::new(165.0, 165.0, 165.0), material.clone()).expect("the short box is not flat");
+    (tall, short)
+}
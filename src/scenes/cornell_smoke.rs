@@ -0,0 +1,45 @@
+use crate::environment::Environment;
+use crate::intersections::{World, XYRect, XZRect, YZRect};
+use crate::material::{DiffuseLight, Lambertian, Material};
+use crate::medium::ConstantMedium;
+use crate::scenes::{cornell_box, Scene};
+use crate::texture::{SolidColor, Texture};
+use crate::transform::Transform;
+use crate::types::{Color, Point3, Vec3};
+
+pub fn scene() -> Scene {
+    Scene {
+        world: make_world(),
+        environment: Environment::Constant(Color::new(0.0, 0.0, 0.0)),
+        vfov: 40.0,
+        aperture: 0.0,
+        lookfrom: Point3::new(278.0, 278.0, -800.0),
+        lookat: Point3::new(278.0, 278.0, 0.0),
+    }
+}
+
+fn make_world() -> World {
+    let mut world = World::new();
+
+    let red = Material::from(Lambertian::from_color(Color::new(0.65, 0.05, 0.05)));
+    let white = Material::from(Lambertian::from_color(Color::new(0.73, 0.73, 0.73)));
+    let green = Material::from(Lambertian::from_color(Color::new(0.12, 0.45, 0.15)));
+    let light = Material::from(DiffuseLight::from_color(Color::new(7.0, 7.0, 7.0)));
+
+    world.add(YZRect { y0: 0.0, y1: 555.0, z0: 0.0, z1: 555.0, k: 555.0, material: green });
+    world.add(YZRect { y0: 0.0, y1: 555.0, z0: 0.0, z1: 555.0, k: 0.0, material: red });
+    world.add(XZRect { x0: 113.0, x1: 443.0, z0: 127.0, z1: 432.0, k: 554.0, material: light });
+    world.add(XZRect { x0: 0.0, x1: 555.0, z0: 0.0, z1: 555.0, k: 0.0, material: white.clone() });
+    world.add(XZRect { x0: 0.0, x1: 555.0, z0: 0.0, z1: 555.0, k: 555.0, material: white.clone() });
+    world.add(XYRect { x0: 0.0, x1: 555.0, y0: 0.0, y1: 555.0, k: 555.0, material: white.clone() });
+
+    let (tall, short) = cornell_box::boxes(&white);
+    let box1 = Transform::new(tall).rotate_y(15.0).translate(Vec3::new(265.0, 0.0, 295.0));
+    let box2 = Transform::new(short).rotate_y(-18.0).translate(Vec3::new(130.0, 0.0, 65.0));
+
+    world.add(ConstantMedium::new(box1, 0.01, Texture::from(SolidColor(Color::new(0.0, 0.0, 0.0)))));
+    world.add(ConstantMedium::new(box2, 0.01, Texture::from(SolidColor(Color::new(1.0, 1.0, 1.0)))));
+
+    world.build_bvh();
+    world
+}
@@ -0,0 +1,752 @@
+//! Declarative TOML scene descriptions.
+//!
+//! ```toml
+//! background = [0.0, 0.0, 0.0]      # or "sky", or an environment map such as
+//!                                   # { image = "sky.hdr", rotation = 90.0, intensity = 1.5 }
+//!
+//! [camera]
+//! lookfrom = [278.0, 278.0, -800.0]
+//! lookat = [278.0, 278.0, 0.0]
+//! vfov = 40.0
+//!
+//! [textures.checker]
+//! type = "checker"
+//! even = [0.2, 0.3, 0.1]
+//! odd = [0.9, 0.9, 0.9]
+//!
+//! [materials.ground]
+//! type = "lambertian"
+//! albedo = "checker"                # texture name or colour
+//!
+//! [[objects]]
+//! type = "sphere"
+//! center = [0.0, -1000.0, 0.0]
+//! radius = 1000.0
+//! material = "ground"
+//! transform = [{ rotate_y = 15.0 }, { translate = [265.0, 0.0, 295.0] }]
+//! ```
+
+use std::cell::Cell;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::iter;
+use std::ops::Range;
+use std::path::Path;
+use std::sync::Arc;
+
+use nalgebra_glm::{self as glm, Mat4};
+use serde::de::value::{MapAccessDeserializer, MapDeserializer};
+use serde::Deserialize;
+use toml::Spanned;
+
+use crate::bvh::Bounded;
+use crate::environment::{Environment, EnvironmentMap};
+use crate::intersections::{Cuboid, Hittable, MovingSphere, Quad, Sphere, World, XYRect, XZRect, YZRect};
+use crate::material::{Dielectric, DiffuseLight, Fresnel, Isotropic, Lambertian, Material, Metal, Microfacet, Principled, PrincipledParameters};
+use crate::medium::ConstantMedium;
+use crate::obj;
+use crate::random::Rng;
+use crate::scenes::Scene;
+use crate::texture::{Checker, ImageTexture, Noise, SolidColor, Texture, TextureFilter, TextureWrap};
+use crate::transform::Transform;
+use crate::types::{Color, Vec3};
+
+#[derive(Debug)]
+pub enum SceneFileError {
+    Io(io::Error),
+    Parse { line: usize, column: usize, message: String },
+}
+
+impl fmt::Display for SceneFileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SceneFileError::Io(e) => write!(f, "{e}"),
+            SceneFileError::Parse { line, column, message } => write!(f, "line {line}, column {column}: {message}"),
+        }
+    }
+}
+
+impl std::error::Error for SceneFileError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SceneFileError::Io(e) => Some(e),
+            SceneFileError::Parse { .. } => None,
+        }
+    }
+}
+
+/// Reads and builds a scene file. Relative paths inside it, such as meshes and
+/// image textures, are resolved against the file's directory. Noise textures
+/// are drawn from `rng`.
+///
+/// # Errors
+///
+/// Fails if the file cannot be read, is not valid TOML, is missing required
+/// fields, has out-of-range values or refers to undefined names.
+pub fn load<P: AsRef<Path>>(path: P, rng: &mut Rng) -> Result<Scene, SceneFileError> {
+    let path = path.as_ref();
+    let source = fs::read_to_string(path).map_err(SceneFileError::Io)?;
+    parse(&source, path.parent().unwrap_or_else(|| Path::new("")), rng)
+}
+
+/// Builds a scene from TOML source, resolving relative paths against `base_dir`.
+///
+/// # Errors
+///
+/// See [`load`].
+pub fn parse(source: &str, base_dir: &Path, rng: &mut Rng) -> Result<Scene, SceneFileError> {
+    let desc: SceneDesc = toml::from_str(source).map_err(|e| {
+        error_at(source, e.span().unwrap_or(0..0), e.message())
+    })?;
+    let objects = desc.objects.iter().map(object_desc).collect::<Result<Vec<_>, _>>()
+        .map_err(|(span, message)| error_at(source, span, &message))?;
+
+    Builder {
+        base_dir,
+        desc: &desc,
+        objects: &objects,
+        rng,
+        textures: HashMap::new(),
+        materials: HashMap::new(),
+        meshes: HashMap::new(),
+    }
+        .build()
+        .map_err(|(span, message)| error_at(source, span, &message))
+}
+
+fn error_at(source: &str, span: Range<usize>, message: &str) -> SceneFileError {
+    let before = &source[..span.start.min(source.len())];
+    let line = before.matches('\n').count() + 1;
+    let column = before.len() - before.rfind('\n').map_or(0, |i| i + 1) + 1;
+    SceneFileError::Parse { line, column, message: message.to_string() }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SceneDesc {
+    camera: CameraDesc,
+    #[serde(default)]
+    background: Option<Spanned<BackgroundDesc>>,
+    #[serde(default)]
+    textures: BTreeMap<String, Spanned<TextureDesc>>,
+    #[serde(default)]
+    materials: BTreeMap<String, Spanned<MaterialDesc>>,
+    #[serde(default)]
+    objects: Vec<Spanned<ObjectFields>>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CameraDesc {
+    lookfrom: [f32; 3],
+    lookat: [f32; 3],
+    vfov: Spanned<f32>,
+    aperture: Option<Spanned<f32>>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum BackgroundDesc {
+    Named(String),
+    Color([f32; 3]),
+    Map(EnvironmentMapDesc),
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct EnvironmentMapDesc {
+    image: String,
+    /// Degrees about the vertical axis.
+    #[serde(default)]
+    rotation: f32,
+    #[serde(default = "one")]
+    intensity: f32,
+}
+
+/// A literal colour or the name of a texture.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ColorOrTexture {
+    Color([f32; 3]),
+    Texture(String),
+}
+
+/// A literal number or the name of a texture whose luminance gives the value.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ScalarOrTexture {
+    Scalar(f32),
+    Texture(String),
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum TextureDesc {
+    Solid { color: [f32; 3] },
+    Checker { even: ColorOrTexture, odd: ColorOrTexture },
+    Noise { scale: f32 },
+    Image {
+        path: String,
+        #[serde(default)]
+        filter: FilterDesc,
+        #[serde(default)]
+        wrap: WrapDesc,
+    },
+}
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+enum FilterDesc {
+    Nearest,
+    #[default]
+    Bilinear,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+enum WrapDesc {
+    #[default]
+    Repeat,
+    Clamp,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum MaterialDesc {
+    Lambertian { albedo: ColorOrTexture },
+    Metal {
+        albedo: [f32; 3],
+        #[serde(default)]
+        fuzz: f32,
+    },
+    Dielectric { ior: f32 },
+    /// A rough metal, either named or given by its complex index of refraction.
+    Conductor {
+        metal: Option<String>,
+        eta: Option<[f32; 3]>,
+        k: Option<[f32; 3]>,
+        roughness: ScalarOrTexture,
+    },
+    /// A rough dielectric coating over a diffuse base.
+    Plastic {
+        albedo: ColorOrTexture,
+        #[serde(default = "plastic_ior")]
+        ior: f32,
+        roughness: ScalarOrTexture,
+    },
+    /// The uber material; parameters left out keep their defaults.
+    Principled {
+        base_color: Option<ColorOrTexture>,
+        metallic: Option<ScalarOrTexture>,
+        roughness: Option<ScalarOrTexture>,
+        specular: Option<ScalarOrTexture>,
+        clearcoat: Option<ScalarOrTexture>,
+        sheen: Option<ScalarOrTexture>,
+        transmission: Option<ScalarOrTexture>,
+        emission: Option<ColorOrTexture>,
+    },
+    DiffuseLight { emit: ColorOrTexture },
+    Isotropic { albedo: ColorOrTexture },
+}
+
+/// An object's keys as written. They are sorted into its shape and the keys
+/// every object takes only once `type` is known, so that a misspelt key is
+/// reported where it is rather than ignored.
+type ObjectFields = BTreeMap<Spanned<String>, toml::Value>;
+
+/// The keys of [`CommonDesc`], which any shape accepts.
+const OBJECT_KEYS: [&str; 3] = ["material", "transform", "medium"];
+
+struct ObjectDesc {
+    span: Range<usize>,
+    shape: ShapeDesc,
+    common: CommonDesc,
+}
+
+#[derive(Deserialize)]
+struct CommonDesc {
+    material: Option<String>,
+    #[serde(default)]
+    transform: Vec<TransformStep>,
+    medium: Option<MediumDesc>,
+}
+
+/// The shape of an object, named by its `type` key.
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+enum ShapeDesc {
+    Sphere { center: [f32; 3], radius: f32 },
+    MovingSphere {
+        center0: [f32; 3],
+        center1: [f32; 3],
+        #[serde(default)]
+        time0: f32,
+        #[serde(default = "one")]
+        time1: f32,
+        radius: f32,
+    },
+    XyRect { x0: f32, x1: f32, y0: f32, y1: f32, k: f32 },
+    XzRect { x0: f32, x1: f32, z0: f32, z1: f32, k: f32 },
+    YzRect { y0: f32, y1: f32, z0: f32, z1: f32, k: f32 },
+    Quad { q: [f32; 3], u: [f32; 3], v: [f32; 3] },
+    Box { min: [f32; 3], max: [f32; 3] },
+    /// Objects loading the same file with the same material share one copy of it.
+    Mesh { path: String },
+}
+
+fn one() -> f32 {
+    1.0
+}
+
+fn plastic_ior() -> f32 {
+    1.5
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+enum TransformStep {
+    Translate([f32; 3]),
+    RotateX(f32),
+    RotateY(f32),
+    RotateZ(f32),
+    Rotate { axis: [f32; 3], degrees: f32 },
+    Scale(ScaleDesc),
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ScaleDesc {
+    Uniform(f32),
+    PerAxis([f32; 3]),
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MediumDesc {
+    density: f32,
+    albedo: ColorOrTexture,
+}
+
+type BuildError = (Range<usize>, String);
+
+struct Builder<'a> {
+    base_dir: &'a Path,
+    desc: &'a SceneDesc,
+    objects: &'a [ObjectDesc],
+    rng: &'a mut Rng,
+    textures: HashMap<&'a str, Texture>,
+    materials: HashMap<&'a str, Material>,
+    /// Loaded meshes by path and material name, shared by all their instances.
+    meshes: HashMap<(&'a str, &'a str), Arc<World>>,
+}
+
+fn vec3(v: [f32; 3]) -> Vec3 {
+    Vec3::new(v[0], v[1], v[2])
+}
+
+fn color(v: [f32; 3], span: &Range<usize>) -> Result<Color, BuildError> {
+    if v.iter().all(|c| c.is_finite() && *c >= 0.0) {
+        Ok(vec3(v))
+    } else {
+        Err((span.clone(), format!("colour {v:?} must have finite, non-negative components")))
+    }
+}
+
+fn positive(value: f32, what: &str, span: &Range<usize>) -> Result<f32, BuildError> {
+    if value.is_finite() && value > 0.0 {
+        Ok(value)
+    } else {
+        Err((span.clone(), format!("{what} must be greater than zero, found {value}")))
+    }
+}
+
+impl<'a> Builder<'a> {
+    fn build(mut self) -> Result<Scene, BuildError> {
+        let camera = &self.desc.camera;
+        let vfov = *camera.vfov.get_ref();
+        if !(vfov > 0.0 && vfov < 180.0) {
+            return Err((camera.vfov.span(), format!("vfov must be between 0 and 180 degrees, found {vfov}")));
+        }
+        let aperture = match &camera.aperture {
+            Some(aperture) if !(aperture.get_ref().is_finite() && *aperture.get_ref() >= 0.0) => {
+                return Err((aperture.span(), format!("aperture must not be negative, found {}", aperture.get_ref())));
+            }
+            Some(aperture) => *aperture.get_ref(),
+            None => 0.0,
+        };
+
+        let environment = match &self.desc.background {
+            None => Environment::Gradient,
+            Some(background) => self.environment(background.get_ref(), &background.span())?,
+        };
+
+        let mut in_progress = HashSet::new();
+        for name in self.desc.textures.keys() {
+            self.texture(name, &mut in_progress)?;
+        }
+        for (name, material) in &self.desc.materials {
+            let built = self.material(material)?;
+            self.materials.insert(name, built);
+        }
+
+        let mut world = World::new();
+        for object in self.objects {
+            self.add_object(&mut world, object)?;
+        }
+        world.build_bvh();
+
+        Ok(Scene {
+            world,
+            environment,
+            lookfrom: vec3(camera.lookfrom),
+            lookat: vec3(camera.lookat),
+            vfov,
+            aperture,
+        })
+    }
+
+    fn texture(&mut self, name: &'a str, in_progress: &mut HashSet<&'a str>) -> Result<Texture, BuildError> {
+        if let Some(texture) = self.textures.get(name) {
+            return Ok(texture.clone());
+        }
+        let desc = &self.desc.textures[name];
+        let span = desc.span();
+        if !in_progress.insert(name) {
+            return Err((span, format!("texture '{name}' refers to itself")));
+        }
+
+        let texture = match desc.get_ref() {
+            TextureDesc::Solid { color: c } => Texture::from(SolidColor(color(*c, &span)?)),
+            TextureDesc::Checker { even, odd } => {
+                let even = self.color_or_texture(even, &span, in_progress)?;
+                let odd = self.color_or_texture(odd, &span, in_progress)?;
+                Texture::from(Checker(Arc::new(even), Arc::new(odd)))
+            }
+            TextureDesc::Noise { scale } => Texture::from(Noise::new(positive(*scale, "noise scale", &span)?, self.rng)),
+            TextureDesc::Image { path, filter, wrap } => {
+                let texture = ImageTexture::open(self.base_dir.join(path))
+                    .map_err(|e| (span.clone(), format!("cannot load image '{path}': {e}")))?;
+                Texture::from(texture
+                    .with_filter(match filter {
+                        FilterDesc::Nearest => TextureFilter::Nearest,
+                        FilterDesc::Bilinear => TextureFilter::Bilinear,
+                    })
+                    .with_wrap(match wrap {
+                        WrapDesc::Repeat => TextureWrap::Repeat,
+                        WrapDesc::Clamp => TextureWrap::Clamp,
+                    }))
+            }
+        };
+
+        in_progress.remove(name);
+        self.textures.insert(name, texture.clone());
+        Ok(texture)
+    }
+
+    fn environment(&self, desc: &BackgroundDesc, span: &Range<usize>) -> Result<Environment, BuildError> {
+        match desc {
+            BackgroundDesc::Named(name) if name == "sky" => Ok(Environment::Gradient),
+            BackgroundDesc::Named(name) => {
+                Err((span.clone(), format!("unknown background '{name}', expected \"sky\", a colour or an environment map")))
+            }
+            BackgroundDesc::Color(c) => Ok(Environment::Constant(color(*c, span)?)),
+            BackgroundDesc::Map(map) => {
+                if !map.rotation.is_finite() {
+                    return Err((span.clone(), format!("rotation must be finite, found {}", map.rotation)));
+                }
+                if !(map.intensity.is_finite() && map.intensity >= 0.0) {
+                    return Err((span.clone(), format!("intensity must not be negative, found {}", map.intensity)));
+                }
+                let loaded = EnvironmentMap::open(self.base_dir.join(&map.image))
+                    .map_err(|e| (span.clone(), format!("cannot load environment map '{}': {e}", map.image)))?;
+                Ok(Environment::Map(loaded.with_rotation(map.rotation).with_intensity(map.intensity)))
+            }
+        }
+    }
+
+    fn color_or_texture(&mut self, value: &'a ColorOrTexture, span: &Range<usize>,
+                        in_progress: &mut HashSet<&'a str>) -> Result<Texture, BuildError> {
+        match value {
+            ColorOrTexture::Color(c) => Ok(Texture::from(SolidColor(color(*c, span)?))),
+            ColorOrTexture::Texture(name) => self.named_texture(name, span, in_progress),
+        }
+    }
+
+    fn scalar_or_texture(&mut self, value: &'a ScalarOrTexture, what: &str, span: &Range<usize>,
+                         in_progress: &mut HashSet<&'a str>) -> Result<Texture, BuildError> {
+        match value {
+            ScalarOrTexture::Scalar(v) if !(0.0..=1.0).contains(v) => {
+                Err((span.clone(), format!("{what} must be between 0 and 1, found {v}")))
+            }
+            ScalarOrTexture::Scalar(v) => Ok(Texture::from(SolidColor(Color::repeat(*v)))),
+            ScalarOrTexture::Texture(name) => self.named_texture(name, span, in_progress),
+        }
+    }
+
+    fn named_texture(&mut self, name: &'a str, span: &Range<usize>,
+                     in_progress: &mut HashSet<&'a str>) -> Result<Texture, BuildError> {
+        if !self.desc.textures.contains_key(name) {
+            return Err((span.clone(), format!("unknown texture '{name}'")));
+        }
+        self.texture(name, in_progress)
+    }
+
+    fn material(&mut self, desc: &'a Spanned<MaterialDesc>) -> Result<Material, BuildError> {
+        let span = desc.span();
+        let mut in_progress = HashSet::new();
+        Ok(match desc.get_ref() {
+            MaterialDesc::Lambertian { albedo } => {
+                Material::from(Lambertian(self.color_or_texture(albedo, &span, &mut in_progress)?))
+            }
+            MaterialDesc::Metal { albedo, fuzz } => {
+                if !(0.0..=1.0).contains(fuzz) {
+                    return Err((span, format!("fuzz must be between 0 and 1, found {fuzz}")));
+                }
+                Material::from(Metal::new(color(*albedo, &span)?, *fuzz))
+            }
+            MaterialDesc::Dielectric { ior } => Material::from(Dielectric(positive(*ior, "ior", &span)?)),
+            MaterialDesc::Conductor { metal, eta, k, roughness } => {
+                let fresnel = match (metal, eta, k) {
+                    (Some(name), None, None) => Fresnel::metal(name).ok_or_else(|| {
+                        (span.clone(), format!("unknown metal '{name}', expected one of {}", Fresnel::METALS.join(", ")))
+                    })?,
+                    (None, Some(eta), Some(k)) => Fresnel::Conductor { eta: color(*eta, &span)?, k: color(*k, &span)? },
+                    _ => return Err((span, "conductor needs either 'metal' or both 'eta' and 'k'".to_string())),
+                };
+                let roughness = self.scalar_or_texture(roughness, "roughness", &span, &mut in_progress)?;
+                Material::from(Microfacet::conductor(fresnel, roughness))
+            }
+            MaterialDesc::Plastic { albedo, ior, roughness } => {
+                let albedo = self.color_or_texture(albedo, &span, &mut in_progress)?;
+                let ior = positive(*ior, "ior", &span)?;
+                let roughness = self.scalar_or_texture(roughness, "roughness", &span, &mut in_progress)?;
+                Material::from(Microfacet::plastic(albedo, ior, roughness))
+            }
+            MaterialDesc::Principled {
+                base_color, metallic, roughness, specular, clearcoat, sheen, transmission, emission
+            } => {
+                let mut principled = PrincipledParameters::default();
+                if let Some(base_color) = base_color {
+                    principled.base_color = self.color_or_texture(base_color, &span, &mut in_progress)?;
+                }
+                let scalars = [
+                    (metallic, "metallic", &mut principled.metallic),
+                    (roughness, "roughness", &mut principled.roughness),
+                    (specular, "specular", &mut principled.specular),
+                    (clearcoat, "clearcoat", &mut principled.clearcoat),
+                    (sheen, "sheen", &mut principled.sheen),
+                    (transmission, "transmission", &mut principled.transmission),
+                ];
+                for (value, what, texture) in scalars {
+                    if let Some(value) = value {
+                        *texture = self.scalar_or_texture(value, what, &span, &mut in_progress)?;
+                    }
+                }
+                if let Some(emission) = emission {
+                    principled.emission = Some(self.color_or_texture(emission, &span, &mut in_progress)?);
+                }
+                Material::from(Principled::new(principled))
+            }
+            MaterialDesc::DiffuseLight { emit } => {
+                Material::from(DiffuseLight(self.color_or_texture(emit, &span, &mut in_progress)?))
+            }
+            MaterialDesc::Isotropic { albedo } => {
+                Material::from(Isotropic(self.color_or_texture(albedo, &span, &mut in_progress)?))
+            }
+        })
+    }
+
+    fn add_object(&mut self, world: &mut World, object: &'a ObjectDesc) -> Result<(), BuildError> {
+        let span = object.span.clone();
+        let desc = &object.common;
+
+        let medium = match &desc.medium {
+            Some(medium) => {
+                let density = positive(medium.density, "medium density", &span)?;
+                let albedo = self.color_or_texture(&medium.albedo, &span, &mut HashSet::new())?;
+                Some((density, albedo))
+            }
+            None => None,
+        };
+
+        let material = match (&desc.material, &medium) {
+            (Some(name), _) => self.materials.get(name.as_str()).cloned()
+                .ok_or_else(|| (span.clone(), format!("unknown material '{name}'")))?,
+            // The boundary of a medium is never shaded, so any material will do
+            (None, Some((_, albedo))) => Material::from(Isotropic(albedo.clone())),
+            (None, None) => return Err((span, "object needs a 'material' or a 'medium'".to_string())),
+        };
+
+        let matrix = transform_matrix(&desc.transform, &span)?;
+        let placement = Placement { matrix, medium };
+
+        match &object.shape {
+            ShapeDesc::Sphere { center, radius } => placement.add(world, Sphere {
+                center: vec3(*center),
+                radius: positive(*radius, "radius", &span)?,
+                material,
+            }),
+            ShapeDesc::MovingSphere { center0, center1, time0, time1, radius } => {
+                if time1 <= time0 {
+                    return Err((span, format!("time1 ({time1}) must be after time0 ({time0})")));
+                }
+                placement.add(world, MovingSphere {
+                    center1: vec3(*center0),
+                    time1: *time0,
+                    center2: vec3(*center1),
+                    time2: *time1,
+                    radius: positive(*radius, "radius", &span)?,
+                    material,
+                });
+            }
+            &ShapeDesc::XyRect { x0, x1, y0, y1, k } => {
+                check_range('x', x0, x1, &span)?;
+                check_range('y', y0, y1, &span)?;
+                placement.add(world, XYRect { x0, x1, y0, y1, k, material });
+            }
+            &ShapeDesc::XzRect { x0, x1, z0, z1, k } => {
+                check_range('x', x0, x1, &span)?;
+                check_range('z', z0, z1, &span)?;
+                placement.add(world, XZRect { x0, x1, z0, z1, k, material });
+            }
+            &ShapeDesc::YzRect { y0, y1, z0, z1, k } => {
+                check_range('y', y0, y1, &span)?;
+                check_range('z', z0, z1, &span)?;
+                placement.add(world, YZRect { y0, y1, z0, z1, k, material });
+            }
+            ShapeDesc::Quad { q, u, v } => {
+                if vec3(*u).cross(&vec3(*v)).magnitude() == 0.0 {
+                    return Err((span, "quad edges u and v must not be parallel".to_string()));
+                }
+                placement.add(world, Quad::new(vec3(*q), vec3(*u), vec3(*v), material));
+            }
+            ShapeDesc::Box { min, max } => {
+                for (axis, (min, max)) in "xyz".chars().zip(min.iter().zip(max)) {
+                    check_range(axis, *min, *max, &span)?;
+                }
+                let cuboid = Cuboid::new(vec3(*min), vec3(*max), material).map_err(|e| (span.clone(), e.to_string()))?;
+                placement.add(world, cuboid);
+            }
+            ShapeDesc::Mesh { path } => {
+                if placement.medium.is_some() {
+                    return Err((span, "a mesh cannot be the boundary of a medium".to_string()));
+                }
+                let key = (path.as_str(), desc.material.as_deref().unwrap_or_default());
+                let geometry = if let Some(geometry) = self.meshes.get(&key) {
+                    geometry.clone()
+                } else {
+                    let mesh = obj::load(self.base_dir.join(path), material)
+                        .map_err(|e| (span.clone(), format!("cannot load mesh '{path}': {e}")))?;
+                    let geometry = Arc::new(World::from_mesh(mesh));
+                    self.meshes.insert(key, geometry.clone());
+                    geometry
+                };
+                world.add_instance(&geometry, &placement.matrix);
+            }
+        }
+        Ok(())
+    }
+}
+
+fn transform_matrix(steps: &[TransformStep], span: &Range<usize>) -> Result<Mat4, BuildError> {
+    let mut matrix = Mat4::identity();
+    for step in steps {
+        let step_matrix = match step {
+            TransformStep::Translate(offset) => glm::translation(&vec3(*offset)),
+            TransformStep::RotateX(degrees) => glm::rotation(degrees.to_radians(), &Vec3::new(1.0, 0.0, 0.0)),
+            TransformStep::RotateY(degrees) => glm::rotation(degrees.to_radians(), &Vec3::new(0.0, 1.0, 0.0)),
+            TransformStep::RotateZ(degrees) => glm::rotation(degrees.to_radians(), &Vec3::new(0.0, 0.0, 1.0)),
+            TransformStep::Rotate { axis, degrees } => {
+                let axis = vec3(*axis);
+                if axis.magnitude() == 0.0 {
+                    return Err((span.clone(), "rotation axis must not be zero".to_string()));
+                }
+                glm::rotation(degrees.to_radians(), &axis.normalize())
+            }
+            TransformStep::Scale(scale) => {
+                let factors = match scale {
+                    ScaleDesc::Uniform(s) => Vec3::new(*s, *s, *s),
+                    ScaleDesc::PerAxis(s) => vec3(*s),
+                };
+                if factors.iter().any(|s| *s == 0.0 || !s.is_finite()) {
+                    return Err((span.clone(), format!("scale factors must be finite and non-zero, found {factors:?}")));
+                }
+                glm::scaling(&factors)
+            }
+        };
+        matrix = step_matrix * matrix;
+    }
+    Ok(matrix)
+}
+
+fn check_range(axis: char, min: f32, max: f32, span: &Range<usize>) -> Result<(), BuildError> {
+    if min < max {
+        Ok(())
+    } else {
+        Err((span.clone(), format!("{axis} bounds {min}..{max} are empty")))
+    }
+}
+
+/// Splits an object's keys into its shape and the keys every object takes.
+/// Errors point at the key being read when there is one, and at the whole
+/// object otherwise, as for a missing key.
+fn object_desc(object: &Spanned<ObjectFields>) -> Result<ObjectDesc, BuildError> {
+    let span = object.span();
+    let fields = object.get_ref();
+    let (type_key, kind) = match fields.get_key_value("type") {
+        Some((key, toml::Value::String(kind))) => (key, kind),
+        Some((key, _)) => return Err((key.span(), "object type must be a string".to_string())),
+        None => return Err((span, "object needs a 'type'".to_string())),
+    };
+    let (common, own): (Vec<_>, Vec<_>) = fields.iter()
+        .filter(|(key, _)| key.get_ref() != "type")
+        .partition(|(key, _)| OBJECT_KEYS.contains(&key.get_ref().as_str()));
+
+    // An unknown type is blamed on the type key
+    let current = Cell::new(Some(type_key.span()));
+    let blame = |e: toml::de::Error| (current.take().unwrap_or_else(|| span.clone()), e.message().to_string());
+
+    let own = MapDeserializer::<_, toml::de::Error>::new(tracked(own, &current));
+    let shape = MapDeserializer::new(iter::once((kind.as_str(), own)));
+    let shape = ShapeDesc::deserialize(MapAccessDeserializer::new(shape)).map_err(blame)?;
+    let common = CommonDesc::deserialize(MapDeserializer::new(tracked(common, &current))).map_err(blame)?;
+    Ok(ObjectDesc { span, shape, common })
+}
+
+/// Yields `keys` for deserializing, keeping the span of the one being read in
+/// `current` and clearing it once they run out.
+fn tracked<'k>(keys: Vec<(&'k Spanned<String>, &'k toml::Value)>, current: &'k Cell<Option<Range<usize>>>)
+    -> impl Iterator<Item = (&'k str, toml::Value)> + 'k {
+    let mut keys = keys.into_iter();
+    iter::from_fn(move || {
+        let next = keys.next();
+        current.set(next.map(|(key, _)| key.span()));
+        next.map(|(key, value)| (key.get_ref().as_str(), value.clone()))
+    })
+}
+
+/// Where and how an object goes into the world once its shape is built.
+struct Placement {
+    matrix: Mat4,
+    medium: Option<(f32, Texture)>,
+}
+
+impl Placement {
+    fn add<T>(self, world: &mut World, shape: T) where T: Hittable + Bounded + Send + Sync + 'static {
+        if self.matrix == Mat4::identity() {
+            self.add_placed(world, shape);
+        } else {
+            let transformed = Transform::new(shape).then(&self.matrix);
+            self.add_placed(world, transformed);
+        }
+    }
+
+    fn add_placed<T>(self, world: &mut World, shape: T) where T: Hittable + Bounded + Send + Sync + 'static {
+        match self.medium {
+            Some((density, albedo)) => world.add(ConstantMedium::new(shape, density, albedo)),
+            None => world.add(shape),
+        }
+    }
+}
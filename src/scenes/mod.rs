@@ -1,14 +1,97 @@
+pub mod cornell_box;
+pub mod cornell_smoke;
+pub mod file;
 pub mod two_perlin_spheres;
 pub mod two_spheres;
 pub mod random_spheres;
 
+use crate::camera::Camera;
+use crate::environment::Environment;
 use crate::intersections::World;
-use crate::types::Point3;
+use crate::random::{pick, Rng};
+use crate::sampler::{PixelSampler, Sampler};
+use crate::types::{Point3, Vec3};
 
 pub struct Scene {
     pub world: World,
+    pub environment: Environment,
     pub lookfrom: Point3,
     pub lookat: Point3,
     pub vfov: f32,
     pub aperture: f32
-}
\ No newline at end of file
+}
+
+impl Scene {
+    pub fn camera(&self, aspect_ratio: f32) -> Camera {
+        let focus_dist = 10.0;
+
+        Camera::new(
+            &self.lookfrom,
+            &self.lookat,
+            &Vec3::new(0.0, 1.0, 0.0),
+            self.vfov,
+            aspect_ratio,
+            self.aperture,
+            focus_dist,
+            0.0,
+            1.0,
+        )
+    }
+
+    /// Number of light sources sampled for direct lighting, counting a
+    /// luminous environment as one.
+    fn light_count(&self) -> usize {
+        self.world.lights.len() + usize::from(self.environment.is_light())
+    }
+
+    /// Density of `sample_light` producing `direction` from `origin`: the
+    /// average over all lights, each being picked with equal probability.
+    pub fn light_pdf(&self, origin: &Point3, direction: &Vec3) -> f32 {
+        let count = self.light_count();
+        if count == 0 {
+            return 0.0;
+        }
+
+        let mut sum: f32 = self.world.lights.iter().map(|light| light.pdf_value(origin, direction)).sum();
+        if self.environment.is_light() {
+            sum += self.environment.pdf_value(direction);
+        }
+        sum / count as f32
+    }
+
+    /// Direction from `origin` towards a light or the environment, both
+    /// picked by `sampler`.
+    pub fn sample_light(&self, origin: &Point3, sampler: &mut PixelSampler) -> Option<Vec3> {
+        let count = self.light_count();
+        if count == 0 {
+            return None;
+        }
+
+        let (index, _) = pick(sampler.get_1d(), count);
+        let u = sampler.get_2d();
+        Some(match self.world.lights.get(index) {
+            Some(light) => light.random(origin, u),
+            None => self.environment.sample(u),
+        })
+    }
+}
+
+pub const NAMES: &[&str] = &[
+    "cornell_box",
+    "cornell_smoke",
+    "random_spheres",
+    "two_spheres",
+    "two_perlin_spheres",
+];
+
+/// Builds a built-in scene, drawing any random layout or textures from `rng`.
+pub fn by_name(name: &str, rng: &mut Rng) -> Option<Scene> {
+    match name {
+        "cornell_box" => Some(cornell_box::scene()),
+        "cornell_smoke" => Some(cornell_smoke::scene()),
+        "random_spheres" => Some(random_spheres::scene(rng)),
+        "two_spheres" => Some(two_spheres::scene()),
+        "two_perlin_spheres" => Some(two_perlin_spheres::scene(rng)),
+        _ => None,
+    }
+}
@@ -1,15 +1,16 @@
-#![allow(dead_code)]
 #![allow(clippy::cast_precision_loss)]
 
+use crate::environment::Environment;
 use crate::intersections::{MovingSphere, Sphere, World};
 use crate::types::{Color, Point3, Vec3};
 use crate::material::{Dielectric, Lambertian, Material, Metal};
-use crate::random::{rand, rand_range};
+use crate::random::Rng;
 use crate::scenes::Scene;
 
-pub fn scene() -> Scene {
+pub fn scene(rng: &mut Rng) -> Scene {
     Scene {
-        world: make_world(),
+        world: make_world(rng),
+        environment: Environment::Gradient,
         vfov: 20.0,
         aperture: 0.1,
         lookfrom: Point3::new(13.0, 2.0, 3.0),
@@ -17,7 +18,7 @@ pub fn scene() -> Scene {
     }
 }
 
-fn make_world() -> World {
+fn make_world(rng: &mut Rng) -> World {
     let mut world = World::new();
 
     let material_ground = Material::from(
@@ -32,12 +33,12 @@ fn make_world() -> World {
 
     for a in -11..11 {
         for b in -11..11 {
-            let choose_mat = rand();
-            let center = Vec3::new(a as f32 + 0.9 * rand(), 0.2, b as f32 + rand());
+            let choose_mat = rng.rand();
+            let center = Vec3::new(a as f32 + 0.9 * rng.rand(), 0.2, b as f32 + rng.rand());
             if (center - Vec3::new(4.0, 0.2, 0.0)).magnitude() > 0.9 {
                 match choose_mat {
                     x if x < 0.8 => {
-                        let center2 = center + Vec3::new(0.0, rand_range(0.0, 0.5), 0.0);
+                        let center2 = center + Vec3::new(0.0, rng.rand_range(0.0, 0.5), 0.0);
                         world.add(MovingSphere {
                             center1: center,
                             center2,
@@ -45,7 +46,7 @@ fn make_world() -> World {
                             time2: 1.0,
                             radius: 0.2,
                             material: Material::from(
-                                Lambertian::from_color(Color::new(rand(), rand(), rand()).component_mul(&Color::new(rand(), rand(), rand())))),
+                                Lambertian::from_color(Color::new(rng.rand(), rng.rand(), rng.rand()).component_mul(&Color::new(rng.rand(), rng.rand(), rng.rand())))),
                         });
                     }
                     x if x < 0.95 => {
@@ -53,8 +54,8 @@ fn make_world() -> World {
                             center,
                             radius: 0.2,
                             material: Material::from(Metal::new(
-                                Color::new(rand_range(0.5, 1.0), rand_range(0.5, 1.0), rand_range(0.5, 1.0)),
-                                rand_range(0.0, 0.5))),
+                                Color::new(rng.rand_range(0.5, 1.0), rng.rand_range(0.5, 1.0), rng.rand_range(0.5, 1.0)),
+                                rng.rand_range(0.0, 0.5))),
                         });
                     }
                     _ => {
@@ -1,14 +1,15 @@
-#![allow(dead_code)]
-
+use crate::environment::Environment;
 use crate::intersections::{Sphere, World};
 use crate::material::{Lambertian, Material};
+use crate::random::Rng;
 use crate::scenes::Scene;
 use crate::types::Point3;
 use crate::texture::{Noise, Texture};
 
-pub fn scene() -> Scene {
+pub fn scene(rng: &mut Rng) -> Scene {
     Scene {
-        world: make_world(),
+        world: make_world(rng),
+        environment: Environment::Gradient,
         vfov: 20.0,
         aperture: 0.0,
         lookfrom: Point3::new(13.0, 2.0, 3.0),
@@ -16,10 +17,10 @@ pub fn scene() -> Scene {
     }
 }
 
-fn make_world() -> World {
+fn make_world(rng: &mut Rng) -> World {
     let mut world = World::new();
 
-    let material_spheres = Material::from(Lambertian(Texture::from(Noise::new(4.0))));
+    let material_spheres = Material::from(Lambertian(Texture::from(Noise::new(4.0, rng))));
 
     world.add(Sphere {
         center: Point3::new(0.0, -1000.0, 0.0),
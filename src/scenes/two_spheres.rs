@@ -1,5 +1,4 @@
-#![allow(dead_code)]
-
+use crate::environment::Environment;
 use crate::intersections::{Sphere, World};
 use crate::material::{Lambertian, Material};
 use crate::scenes::Scene;
@@ -8,6 +7,7 @@ use crate::types::{Color, Point3};
 pub fn scene() -> Scene {
     Scene {
         world: make_world(),
+        environment: Environment::Gradient,
         vfov: 20.0,
         aperture: 0.0,
         lookfrom: Point3::new(13.0, 2.0, 3.0),
@@ -1,14 +1,18 @@
 use crate::types::{Point3, Color};
 use enum_dispatch::enum_dispatch;
+use std::path::Path;
 use std::sync::Arc;
 use crate::perlin::Perlin;
+use crate::random::Rng;
+use crate::tonemap::srgb_to_linear;
 
 #[enum_dispatch]
 #[derive(Clone)]
 pub enum Texture {
     SolidColor,
     Checker,
-    Noise
+    Noise,
+    ImageTexture,
 }
 
 #[enum_dispatch(Texture)]
@@ -46,8 +50,8 @@ impl GetColor for Checker {
 pub struct Noise(f32, Perlin);
 
 impl Noise {
-    pub fn new(scale: f32) -> Self {
-        Self(scale, Perlin::new())
+    pub fn new(scale: f32, rng: &mut Rng) -> Self {
+        Self(scale, Perlin::new(rng))
     }
 }
 
@@ -55,4 +59,121 @@ impl GetColor for Noise {
     fn value(&self, _u: f32, _v: f32, p: Point3) -> Color {
         Color::new(1.0, 1.0, 1.0) * 0.5 * (1.0 + (self.0*p.z + 10.0 * self.1.turb(p, 7)).sin())
     }
-}
\ No newline at end of file
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TextureFilter {
+    Nearest,
+    Bilinear,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TextureWrap {
+    Repeat,
+    Clamp,
+}
+
+struct Texels {
+    width: usize,
+    height: usize,
+    data: Vec<Color>,
+}
+
+/// A bitmap mapped onto the surface by its `(u, v)` coordinates, with `v = 0`
+/// at the bottom row. Texels are stored in linear colour.
+#[derive(Clone)]
+pub struct ImageTexture {
+    texels: Option<Arc<Texels>>,
+    pub filter: TextureFilter,
+    pub wrap: TextureWrap,
+}
+
+impl ImageTexture {
+    /// Decodes a PNG or JPEG file, treating its contents as sRGB encoded.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the file cannot be read or decoded.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, ::image::ImageError> {
+        let decoded = ::image::open(path)?.into_rgb32f();
+        let data = decoded
+            .pixels()
+            .map(|p| Color::new(srgb_to_linear(p[0]), srgb_to_linear(p[1]), srgb_to_linear(p[2])))
+            .collect();
+
+        Ok(ImageTexture {
+            texels: Some(Arc::new(Texels {
+                width: decoded.width() as usize,
+                height: decoded.height() as usize,
+                data,
+            })),
+            filter: TextureFilter::Bilinear,
+            wrap: TextureWrap::Repeat,
+        })
+    }
+
+    /// Like `open`, but reports a failure on stderr and returns a texture that
+    /// renders as solid magenta instead.
+    pub fn load<P: AsRef<Path>>(path: P) -> Self {
+        Self::open(&path).unwrap_or_else(|e| {
+            eprintln!("warning: could not load texture '{}': {e}", path.as_ref().display());
+            ImageTexture {
+                texels: None,
+                filter: TextureFilter::Bilinear,
+                wrap: TextureWrap::Repeat,
+            }
+        })
+    }
+
+    #[must_use]
+    pub fn with_filter(mut self, filter: TextureFilter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    #[must_use]
+    pub fn with_wrap(mut self, wrap: TextureWrap) -> Self {
+        self.wrap = wrap;
+        self
+    }
+
+    #[allow(clippy::cast_possible_wrap)]
+    fn wrap_index(&self, i: isize, size: usize) -> usize {
+        match self.wrap {
+            TextureWrap::Repeat => i.rem_euclid(size as isize) as usize,
+            TextureWrap::Clamp => i.clamp(0, size as isize - 1) as usize,
+        }
+    }
+}
+
+impl GetColor for ImageTexture {
+    #[allow(clippy::many_single_char_names)]
+    fn value(&self, u: f32, v: f32, _p: Point3) -> Color {
+        let Some(texels) = &self.texels else {
+            return Color::new(1.0, 0.0, 1.0);
+        };
+
+        let x = u * texels.width as f32;
+        let y = (1.0 - v) * texels.height as f32;
+        let texel = |i: isize, j: isize| {
+            texels.data[self.wrap_index(j, texels.height) * texels.width + self.wrap_index(i, texels.width)]
+        };
+
+        match self.filter {
+            TextureFilter::Nearest => texel(x.floor() as isize, y.floor() as isize),
+            TextureFilter::Bilinear => {
+                let x = x - 0.5;
+                let y = y - 0.5;
+                let i = x.floor();
+                let j = y.floor();
+                let fx = x - i;
+                let fy = y - j;
+                let (i, j) = (i as isize, j as isize);
+
+                let top = (1.0 - fx) * texel(i, j) + fx * texel(i + 1, j);
+                let bottom = (1.0 - fx) * texel(i, j + 1) + fx * texel(i + 1, j + 1);
+                (1.0 - fy) * top + fy * bottom
+            }
+        }
+    }
+}
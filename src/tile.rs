@@ -0,0 +1,95 @@
+//! Division of the image into square tiles and the order they are rendered in.
+
+/// A rectangle of pixels, counted from the image's top left corner.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Tile {
+    pub left: u32,
+    pub top: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TileOrder {
+    /// Row by row from the top left.
+    Scanline,
+    /// Outwards from the centre, where the subject usually is.
+    Spiral,
+    /// Along a Hilbert curve, which keeps consecutive tiles next to each
+    /// other and so shares more of the scene between them in the caches.
+    Hilbert,
+}
+
+/// Covers a `width` by `height` image with tiles of `size` pixels, smaller
+/// along the right and bottom edges, and lists them in the given order.
+pub fn tiles(width: u32, height: u32, size: u32, order: TileOrder) -> Vec<Tile> {
+    let size = size.max(1);
+    let columns = width.div_ceil(size);
+    let rows = height.div_ceil(size);
+    let tile = |(column, row): (u32, u32)| {
+        let (left, top) = (column * size, row * size);
+        Tile { left, top, width: size.min(width - left), height: size.min(height - top) }
+    };
+
+    match order {
+        TileOrder::Scanline => (0..rows).flat_map(|row| (0..columns).map(move |column| (column, row))).map(tile).collect(),
+        TileOrder::Spiral => spiral(columns, rows).into_iter().map(tile).collect(),
+        TileOrder::Hilbert => hilbert(columns, rows).into_iter().map(tile).collect(),
+    }
+}
+
+/// Cells of a `columns` by `rows` grid, walking a square spiral out from the
+/// centre and skipping the turns that fall outside the grid.
+fn spiral(columns: u32, rows: u32) -> Vec<(u32, u32)> {
+    let total = (columns * rows) as usize;
+    let mut cells = Vec::with_capacity(total);
+    let (mut x, mut y) = (i64::from((columns - 1) / 2), i64::from((rows - 1) / 2));
+    let directions = [(1, 0), (0, 1), (-1, 0), (0, -1)];
+    let mut leg = 0;
+    while cells.len() < total {
+        // Legs grow by one every second turn: 1, 1, 2, 2, 3, 3, ...
+        let (dx, dy) = directions[leg % 4];
+        for _ in 0..=leg / 2 {
+            if (0..i64::from(columns)).contains(&x) && (0..i64::from(rows)).contains(&y) {
+                cells.push((x as u32, y as u32));
+            }
+            x += dx;
+            y += dy;
+        }
+        leg += 1;
+    }
+    cells
+}
+
+/// Cells of a `columns` by `rows` grid in the order a Hilbert curve over the
+/// enclosing power of two square visits them.
+fn hilbert(columns: u32, rows: u32) -> Vec<(u32, u32)> {
+    let side = columns.max(rows).next_power_of_two();
+    (0..u64::from(side) * u64::from(side))
+        .map(|d| hilbert_cell(side, d))
+        .filter(|&(x, y)| x < columns && y < rows)
+        .collect()
+}
+
+/// Cell at distance `d` along the Hilbert curve through a `side` by `side` square.
+fn hilbert_cell(side: u32, mut d: u64) -> (u32, u32) {
+    let (mut x, mut y) = (0, 0);
+    let mut s = 1;
+    while s < side {
+        let rx = ((d / 2) & 1) as u32;
+        let ry = (d ^ u64::from(rx)) as u32 & 1;
+        // Rotate the quadrant so the sub-curves join up
+        if ry == 0 {
+            if rx == 1 {
+                x = s - 1 - x;
+                y = s - 1 - y;
+            }
+            std::mem::swap(&mut x, &mut y);
+        }
+        x += s * rx;
+        y += s * ry;
+        d /= 4;
+        s *= 2;
+    }
+    (x, y)
+}
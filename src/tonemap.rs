@@ -0,0 +1,107 @@
+use crate::types::Color;
+
+/// Curve compressing scene radiance into the displayable `[0, 1]` range.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ToneMapOperator {
+    /// Clips everything above 1.
+    Clamp,
+    Reinhard,
+    /// Reinhard with a `white` level that maps exactly to 1.
+    ExtendedReinhard { white: f32 },
+    /// Narkowicz's fit of the ACES filmic reference transform.
+    Aces,
+    /// John Hable's filmic curve from Uncharted 2.
+    Hable,
+}
+
+/// Encoding applied to the tone mapped values before quantisation.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum TransferFunction {
+    Srgb,
+    Gamma(f32),
+}
+
+/// Post-processing turning linear radiance into display values.
+///
+/// The steps run in order: white balance, exposure, tone curve, transfer
+/// function.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ToneMapping {
+    /// Exposure adjustment in stops.
+    pub exposure: f32,
+    /// Colour of a surface that should come out neutral grey.
+    pub white_balance: Color,
+    pub operator: ToneMapOperator,
+    pub transfer: TransferFunction,
+}
+
+impl Default for ToneMapping {
+    fn default() -> Self {
+        ToneMapping {
+            exposure: 0.0,
+            white_balance: Color::new(1.0, 1.0, 1.0),
+            operator: ToneMapOperator::Clamp,
+            transfer: TransferFunction::Srgb,
+        }
+    }
+}
+
+impl ToneMapping {
+    pub fn apply(&self, color: Color) -> Color {
+        let gains = self.white_balance.map(|c| luminance(self.white_balance) / c);
+        let exposed = color.component_mul(&gains) * self.exposure.exp2();
+        exposed.map(|c| self.transfer.encode(self.operator.apply(c.max(0.0)).min(1.0)))
+    }
+}
+
+impl ToneMapOperator {
+    pub fn apply(&self, x: f32) -> f32 {
+        match *self {
+            ToneMapOperator::Clamp => x,
+            ToneMapOperator::Reinhard => x / (1.0 + x),
+            ToneMapOperator::ExtendedReinhard { white } => x * (1.0 + x / (white * white)) / (1.0 + x),
+            ToneMapOperator::Aces => (x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14),
+            ToneMapOperator::Hable => {
+                const EXPOSURE_BIAS: f32 = 2.0;
+                const WHITE: f32 = 11.2;
+                hable_partial(EXPOSURE_BIAS * x) / hable_partial(WHITE)
+            }
+        }
+    }
+}
+
+#[allow(clippy::many_single_char_names)]
+fn hable_partial(x: f32) -> f32 {
+    let (a, b, c, d, e, f) = (0.15, 0.50, 0.10, 0.20, 0.02, 0.30);
+    ((x * (a * x + c * b) + d * e) / (x * (a * x + b) + d * f)) - e / f
+}
+
+impl TransferFunction {
+    pub fn encode(&self, x: f32) -> f32 {
+        match *self {
+            TransferFunction::Srgb => linear_to_srgb(x),
+            TransferFunction::Gamma(gamma) => x.powf(1.0 / gamma),
+        }
+    }
+}
+
+/// Relative luminance of a linear Rec. 709 colour.
+pub fn luminance(color: Color) -> f32 {
+    0.2126 * color.x + 0.7152 * color.y + 0.0722 * color.z
+}
+
+pub fn linear_to_srgb(c: f32) -> f32 {
+    if c <= 0.003_130_8 {
+        12.92 * c
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+pub fn srgb_to_linear(c: f32) -> f32 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
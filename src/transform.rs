@@ -0,0 +1,195 @@
+use std::sync::Arc;
+
+use nalgebra_glm::{self as glm, Mat3, Mat4};
+
+use crate::bvh::{Aabb, Bounded};
+use crate::intersections::{HitRecord, Hittable, World};
+use crate::types::{Point3, Ray, Vec3};
+
+/// Places a shared object in the world through an affine object-to-world matrix.
+///
+/// Rays are moved into object space with the inverse matrix, so the wrapped
+/// object never needs to know where it has been placed. The direction is not
+/// renormalised, which keeps hit distances valid in both spaces.
+#[derive(Clone)]
+pub struct Transform {
+    inner: Arc<dyn Hittable + Send + Sync>,
+    inner_aabb: Aabb,
+    matrix: Mat4,
+    inverse: Mat4,
+    normal_matrix: Mat3,
+}
+
+impl Transform {
+    pub fn new<T>(obj: T) -> Transform where T: Hittable + Bounded + Send + Sync + 'static {
+        let inner_aabb = obj.aabb();
+        Self::shared(Arc::new(obj), inner_aabb)
+    }
+
+    /// Wraps an object that is already shared between several transforms.
+    pub fn shared(inner: Arc<dyn Hittable + Send + Sync>, inner_aabb: Aabb) -> Transform {
+        Transform {
+            inner,
+            inner_aabb,
+            matrix: Mat4::identity(),
+            inverse: Mat4::identity(),
+            normal_matrix: Mat3::identity(),
+        }
+    }
+
+    /// Applies `matrix` after the transformation accumulated so far.
+    #[must_use]
+    pub fn then(mut self, matrix: &Mat4) -> Transform {
+        self.matrix = matrix * self.matrix;
+        self.inverse = glm::inverse(&self.matrix);
+        self.normal_matrix = glm::mat4_to_mat3(&self.inverse).transpose();
+        self
+    }
+
+    #[must_use]
+    pub fn translate(self, offset: Vec3) -> Transform {
+        self.then(&glm::translation(&offset))
+    }
+
+    #[must_use]
+    pub fn rotate(self, axis: Vec3, degrees: f32) -> Transform {
+        self.then(&glm::rotation(degrees.to_radians(), &axis))
+    }
+
+    #[must_use]
+    pub fn rotate_x(self, degrees: f32) -> Transform {
+        self.rotate(Vec3::new(1.0, 0.0, 0.0), degrees)
+    }
+
+    #[must_use]
+    pub fn rotate_y(self, degrees: f32) -> Transform {
+        self.rotate(Vec3::new(0.0, 1.0, 0.0), degrees)
+    }
+
+    #[must_use]
+    pub fn rotate_z(self, degrees: f32) -> Transform {
+        self.rotate(Vec3::new(0.0, 0.0, 1.0), degrees)
+    }
+
+    #[must_use]
+    pub fn scale(self, factors: Vec3) -> Transform {
+        self.then(&glm::scaling(&factors))
+    }
+
+    #[must_use]
+    pub fn scale_uniform(self, factor: f32) -> Transform {
+        self.scale(Vec3::new(factor, factor, factor))
+    }
+
+    pub fn matrix(&self) -> &Mat4 {
+        &self.matrix
+    }
+
+    fn transform_point(m: &Mat4, p: &Point3) -> Point3 {
+        (m * glm::vec4(p.x, p.y, p.z, 1.0)).xyz()
+    }
+
+    fn transform_vector(m: &Mat4, v: &Vec3) -> Vec3 {
+        (m * glm::vec4(v.x, v.y, v.z, 0.0)).xyz()
+    }
+}
+
+impl Hittable for Transform {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+        let mut local_ray = Ray::new(Self::transform_point(&self.inverse, &ray.origin()),
+                                     Self::transform_vector(&self.inverse, &ray.direction()),
+                                     ray.time);
+        local_ray.medium_sample = ray.medium_sample;
+
+        let mut hit = self.inner.hit(&local_ray, t_min, t_max)?;
+        hit.point = Self::transform_point(&self.matrix, &hit.point);
+        hit.normal = (self.normal_matrix * hit.normal).normalize();
+        Some(hit)
+    }
+
+    fn is_light(&self) -> bool {
+        self.inner.is_light()
+    }
+
+    // Solid angles are only preserved by rotations, translations and uniform
+    // scaling, so light sampling through a shearing or non-uniformly scaling
+    // transform is approximate.
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3) -> f32 {
+        self.inner.pdf_value(&Self::transform_point(&self.inverse, origin),
+                             &Self::transform_vector(&self.inverse, direction))
+    }
+
+    fn random(&self, origin: &Point3, u: (f32, f32)) -> Vec3 {
+        let local = self.inner.random(&Self::transform_point(&self.inverse, origin), u);
+        Self::transform_vector(&self.matrix, &local)
+    }
+}
+
+impl Bounded for Transform {
+    fn aabb(&self) -> Aabb {
+        let lo = self.inner_aabb.min;
+        let hi = self.inner_aabb.max;
+
+        let mut min = Point3::repeat(f32::INFINITY);
+        let mut max = Point3::repeat(f32::NEG_INFINITY);
+        for i in 0..8 {
+            let corner = Point3::new(if i & 1 == 0 { lo.x } else { hi.x },
+                                     if i & 2 == 0 { lo.y } else { hi.y },
+                                     if i & 4 == 0 { lo.z } else { hi.z });
+            let p = Self::transform_point(&self.matrix, &corner);
+            min = min.inf(&p);
+            max = max.sup(&p);
+        }
+
+        Aabb::new(min, max)
+    }
+}
+
+impl World {
+    /// Adds a copy of `geometry` placed by `matrix`. All copies share the
+    /// geometry's own hierarchy, which must already be built, and the world's
+    /// hierarchy only bounds the copies as a whole. A copy left where it is
+    /// goes in without a `Transform`, sparing its rays the change of space.
+    pub fn add_instance(&mut self, geometry: &Arc<World>, matrix: &Mat4) {
+        if *matrix == Mat4::identity() {
+            self.add_shared(geometry.clone(), geometry.aabb());
+        } else {
+            self.add(Transform::shared(geometry.clone(), geometry.aabb()).then(matrix));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intersections::Sphere;
+    use crate::material::{Lambertian, Material};
+    use crate::types::Color;
+
+    #[test]
+    fn instances_share_one_world() {
+        let mut geometry = World::new();
+        geometry.add(Sphere {
+            center: Point3::new(0.0, 0.0, 0.0),
+            radius: 1.0,
+            material: Material::from(Lambertian::from_color(Color::new(0.5, 0.5, 0.5))),
+        });
+        geometry.build_bvh();
+        let geometry = Arc::new(geometry);
+
+        let mut world = World::new();
+        for x in [0.0, 5.0, 10.0] {
+            world.add_instance(&geometry, &glm::translation(&Vec3::new(x, 0.0, 0.0)));
+        }
+        world.build_bvh();
+        assert_eq!(Arc::strong_count(&geometry), 4);
+
+        for x in [0.0, 5.0, 10.0] {
+            let ray = Ray::new(Point3::new(x, 0.0, 10.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+            let hit = world.hit(&ray, 0.001, f32::INFINITY);
+            assert!(hit.is_some_and(|hit| (hit.t - 9.0).abs() < 1e-4 && (hit.point.x - x).abs() < 1e-4), "no hit at x = {}", x);
+        }
+        let between = Ray::new(Point3::new(2.5, 0.0, 10.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        assert!(world.hit(&between, 0.001, f32::INFINITY).is_none());
+    }
+}
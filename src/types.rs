@@ -4,27 +4,71 @@ pub type Color = glm::Vec3;
 pub type Point3 = glm::Vec3;
 pub type Vec3 = glm::Vec3;
 
+#[derive(Clone, Copy)]
 pub struct Ray {
     origin : Point3,
     direction : Vec3,
-    pub(crate) time: f32
+    pub time: f32,
+    /// Uniform number deciding how far the ray gets into participating media
+    /// before it scatters, drawn afresh for every ray traced. Each medium the
+    /// ray crosses scrambles it into a number of its own. Rays without one
+    /// pass through media as if they were not there.
+    pub medium_sample: Option<f32>
 }
 
 impl Ray {
-    pub(crate) fn new(origin : Point3, direction : Vec3, time: f32) -> Ray {
-        Ray { origin, direction, time }
+    /// A ray that passes through participating media; give it a sample with
+    /// `with_medium_sample` for it to scatter inside them.
+    pub fn new(origin : Point3, direction : Vec3, time: f32) -> Ray {
+        Ray { origin, direction, time, medium_sample: None }
     }
 
-    pub(crate) fn origin(&self) -> Point3 {
+    #[must_use]
+    pub fn with_medium_sample(self, medium_sample: f32) -> Ray {
+        Ray { medium_sample: Some(medium_sample), ..self }
+    }
+
+    pub fn origin(&self) -> Point3 {
         self.origin
     }
 
-    pub(crate) fn direction(&self) -> Vec3 {
+    pub fn direction(&self) -> Vec3 {
         self.direction
     }
 
-    pub(crate) fn at(&self, t: f32) -> Point3 {
+    pub fn at(&self, t: f32) -> Point3 {
         self.origin + t * self.direction
     }
 }
 
+/// Orthonormal basis whose `w` axis follows a given direction, used to turn
+/// directions sampled about `+z` into world space.
+#[derive(Clone, Copy)]
+pub struct Onb {
+    u: Vec3,
+    v: Vec3,
+    w: Vec3,
+}
+
+impl Onb {
+    pub fn from_w(direction: &Vec3) -> Onb {
+        let w = direction.normalize();
+        let helper = if w.x.abs() > 0.9 { Vec3::new(0.0, 1.0, 0.0) } else { Vec3::new(1.0, 0.0, 0.0) };
+        let v = w.cross(&helper).normalize();
+        let u = w.cross(&v);
+        Onb { u, v, w }
+    }
+
+    pub fn w(&self) -> Vec3 {
+        self.w
+    }
+
+    pub fn local(&self, a: &Vec3) -> Vec3 {
+        a.x * self.u + a.y * self.v + a.z * self.w
+    }
+
+    /// Inverse of `local`: expresses a world space vector in the basis.
+    pub fn to_local(&self, a: &Vec3) -> Vec3 {
+        Vec3::new(a.dot(&self.u), a.dot(&self.v), a.dot(&self.w))
+    }
+}